@@ -0,0 +1,51 @@
+//! Measures how many steps per second the machine runs on a
+//! compute-heavy program, and on a sparse program with and without
+//! fast-forwarding over spaces. Run this example with the '--release' flag!
+
+use std::time::Instant;
+
+use msc::{load, machine::State};
+
+const STEPS: usize = 50_000_000;
+
+// Counts up in the register forever, using the stack on each loop
+const PROGRAM: &str = "#
+s 0 0 1
+>d+v
+^  <
+#";
+
+// The same loop, but with a large blank region to cross
+const SPARSE_PROGRAM: &str = "#
+s 0 0 1
+>d+                                                              v
+
+
+
+^                                                                <
+#";
+
+fn bench(name: &str, program: &str, fast_forward: bool) {
+    let mut machine = load::from_str::<i32, _>(program, &|value| value.parse()).unwrap();
+    machine.set_step_limit(Some(STEPS));
+    machine.set_fast_forward(fast_forward);
+
+    let start = Instant::now();
+    while matches!(machine.get_state(), State::Running) {
+        machine.step();
+    }
+    let elapsed = start.elapsed();
+
+    #[allow(clippy::cast_precision_loss)]
+    let steps_per_second = machine.get_steps() as f64 / elapsed.as_secs_f64();
+    println!(
+        "{name}: {} steps in {elapsed:?} ({steps_per_second:.0} steps/second)",
+        machine.get_steps()
+    );
+}
+
+fn main() {
+    bench("dense", PROGRAM, false);
+    bench("sparse", SPARSE_PROGRAM, false);
+    bench("sparse, fast-forward", SPARSE_PROGRAM, true);
+}
@@ -2,7 +2,7 @@
 //! that will run in no_std mode
 
 use core::num::{ParseIntError, TryFromIntError, Wrapping};
-use msc::{build, machine::State};
+use msc::{build_machine, machine::State};
 
 const PROGRAM_SIZE: (usize, usize) = (8, 6);
 const STACK_CAPACITY: usize = 2;
@@ -23,16 +23,17 @@ fn main() {
     #[cfg(feature = "std")]
     println!("Try running this example with the '--no-default-features' flag!");
 
-    let mut machine = build::from_str::<
-        N,
-        { PROGRAM_SIZE.0 },
-        { PROGRAM_SIZE.1 },
-        STACK_CAPACITY,
-        { STACK_SIZE.0 },
-        { STACK_SIZE.1 },
-        ParseIntError,
-        TryFromIntError
-    >(PROGRAM, &try_parse_n, &try_n_to_usize)
+    let mut machine = build_machine! {
+        source: PROGRAM,
+        number: N,
+        width: PROGRAM_SIZE.0,
+        height: PROGRAM_SIZE.1,
+        stack_capacity: STACK_CAPACITY,
+        stack_width: STACK_SIZE.0,
+        stack_height: STACK_SIZE.1,
+        parse: &try_parse_n,
+        to_usize: &try_n_to_usize,
+    }
     .unwrap();
 
     while matches!(machine.get_state(), State::Running) {
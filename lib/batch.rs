@@ -0,0 +1,132 @@
+//! Run many independent `MSCode` machines, each with its own inputs.
+//! Requires std
+
+use std::thread;
+
+use crate::{
+    io::{Channel, SliceInput},
+    load::Machine,
+    machine::State,
+    Number,
+};
+
+/// Run each machine until it stops, is halted or runs out of inputs,
+/// returning the outputs of each machine on the
+/// [`Primary`](Channel::Primary) channel.
+///
+/// Each machine reads from the inputs at the same index, or from no inputs
+/// if there are fewer input lists than machines. If `step_limit` is set,
+/// it replaces the step limit of every machine
+///
+/// # Panics
+/// Panics if a machine's pointer is over a stack that is not in its
+/// stack plane
+#[must_use]
+pub fn run_many<N: Number>(
+    machines: Vec<Machine<N>>,
+    inputs: Vec<Vec<N>>,
+    step_limit: Option<usize>,
+) -> Vec<Vec<N>> {
+    let mut inputs = inputs.into_iter();
+    machines
+        .into_iter()
+        .map(|machine| run_one(machine, &inputs.next().unwrap_or_default(), step_limit))
+        .collect()
+}
+
+/// Run machines like [`run_many`], each on its own thread
+///
+/// # Panics
+/// Panics if a machine's pointer is over a stack that is not in its
+/// stack plane
+#[must_use]
+pub fn run_many_threaded<N: Number + Send>(
+    machines: Vec<Machine<N>>,
+    inputs: Vec<Vec<N>>,
+    step_limit: Option<usize>,
+) -> Vec<Vec<N>> {
+    let mut inputs = inputs.into_iter();
+    thread::scope(|scope| {
+        let handles: Vec<_> = machines
+            .into_iter()
+            .map(|machine| {
+                let inputs = inputs.next().unwrap_or_default();
+                scope.spawn(move || run_one(machine, &inputs, step_limit))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| match handle.join() {
+                Ok(outputs) => outputs,
+                // Pass a machine's panic on to the caller
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    })
+}
+
+/// Run one machine with its inputs, collecting its outputs
+fn run_one<N: Number>(mut machine: Machine<N>, inputs: &[N], step_limit: Option<usize>) -> Vec<N> {
+    if step_limit.is_some() {
+        machine.set_step_limit(step_limit);
+    }
+
+    let mut input = SliceInput::new(inputs);
+    let mut outputs = Vec::new();
+    while machine.get_state() == State::Running {
+        if let Some(output) = machine.step_with_input(&mut input) {
+            if output.channel() == Channel::Primary {
+                outputs.extend(output.value().copied());
+            }
+        }
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod test {
+    use crate::load::{self, Machine};
+
+    use super::{run_many, run_many_threaded};
+
+    fn load(source: &str) -> Machine<i32> {
+        load::from_str(source, &|value| value.parse::<i32>()).unwrap()
+    }
+
+    fn machines() -> Vec<Machine<i32>> {
+        vec![
+            // Double the input
+            load("i,+p"),
+            // Print two inputs, then one that is not given
+            load("ipipip"),
+            // Print forever
+            load(">pv\n^ <"),
+        ]
+    }
+
+    #[test]
+    fn many() {
+        let outputs = run_many(machines(), vec![vec![21], vec![1, 2]], Some(10));
+
+        assert_eq!(outputs, [vec![42], vec![1, 2], vec![0, 0]]);
+    }
+
+    #[test]
+    fn many_threaded() {
+        let inputs = vec![vec![21], vec![1, 2]];
+
+        assert_eq!(
+            run_many_threaded(machines(), inputs.clone(), Some(10)),
+            run_many(machines(), inputs, Some(10))
+        );
+    }
+
+    #[test]
+    fn many_keeps_step_limits() {
+        let mut machine = load(">pv\n^ <");
+        machine.set_step_limit(Some(4));
+
+        assert_eq!(run_many(vec![machine], Vec::new(), None), [vec![0]]);
+    }
+}
@@ -5,7 +5,7 @@ use core::{fmt::Display, iter::once};
 
 use crate::{
     instruction::{Instruction, IntoInstructionError},
-    machine,
+    is_shebang, machine,
     plane::{ArrayPlane, Plane},
     stack::{ArrayStack, Stack},
     Number, Pointer,
@@ -20,12 +20,22 @@ pub enum Error<ParseNError: Display, NToUsizeError: Display> {
     InstructionOutOfRange(Pointer, char),
     /// Invalid number
     InvalidNumber(ParseNError),
-    /// Invalid coordinate number
+    /// Coordinate number that cannot be converted to a `usize`,
+    /// such as a negative number or one greater than `usize::MAX`
     InvalidCoordinate(NToUsizeError),
-    /// Stack coordinate greater than or equal to 1/4 of the width / height
+    /// Stack coordinate that fits in a `usize`, but is outside of the
+    /// stack plane (greater than or equal to the stack width / height)
     StackPointerOutOfRange(Pointer),
     /// Missing at least one coordinate in a stack line
     MissingStackPointer,
+    /// Missing the coordinates or capacity in a capacity line
+    MissingCapacity,
+    /// An extra token at the end of a header line. Only returned when
+    /// converting from a strict `load::Error`
+    StrayToken,
+    /// Failed to read the source (<span style="color: var(--codeblock-error-hover-color);">REQUIRES STD!</span>)
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
 }
 
 /// <span style="color: var(--codeblock-error-hover-color);">
@@ -41,6 +51,7 @@ impl<PNE: std::error::Error + 'static, NUE: std::error::Error + 'static> std::er
             InvalidInstruction(err) => Some(err),
             InvalidNumber(err) => Some(err),
             InvalidCoordinate(err) => Some(err),
+            Self::Io(err) => Some(err),
             _ => None,
         }
     }
@@ -50,7 +61,7 @@ impl<PNE: Display, NUE: Display> Display for Error<PNE, NUE> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Error::{
             InstructionOutOfRange, InvalidCoordinate, InvalidInstruction, InvalidNumber,
-            MissingStackPointer, StackPointerOutOfRange,
+            MissingCapacity, MissingStackPointer, StackPointerOutOfRange, StrayToken,
         };
         match self {
             InvalidInstruction(err) => err.fmt(f),
@@ -58,11 +69,15 @@ impl<PNE: Display, NUE: Display> Display for Error<PNE, NUE> {
                 write!(f, "instruction out of range: {pointer:?} ('{char}')")
             }
             InvalidNumber(err) => err.fmt(f),
-            InvalidCoordinate(err) => err.fmt(f),
+            InvalidCoordinate(err) => write!(f, "invalid coordinate: {err}"),
             StackPointerOutOfRange(pointer) => {
                 write!(f, "stack pointer out of range: {pointer:?}")
             }
             MissingStackPointer => write!(f, "stack line missing at least one coordinate"),
+            MissingCapacity => write!(f, "capacity line missing coordinates or capacity"),
+            StrayToken => write!(f, "unexpected token at the end of a header line"),
+            #[cfg(feature = "std")]
+            Self::Io(err) => write!(f, "failed to read source: {err}"),
         }
     }
 }
@@ -94,9 +109,10 @@ pub type Machine<
 /// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
 /// - [`Error::InstructionOutOfRange`] - instruction out of width and height set as constants
 /// - [`Error::InvalidNumber`] - failed to parse a number
-/// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
-/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
+/// - [`Error::InvalidCoordinate`] - failed to convert a coordinate number to a `usize`
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is outside of the stack plane
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
 pub fn from_str<
     N: Number,
     const WIDTH: usize,
@@ -120,7 +136,11 @@ pub fn from_str<
     // The code body line number
     let mut y = 0;
 
-    for line in source.lines() {
+    let mut lines = source.lines().peekable();
+    // Skip the shebang, if there is one
+    lines.next_if(|line| is_shebang(line));
+
+    for line in lines {
         parse_line(
             line,
             &mut y,
@@ -134,6 +154,59 @@ pub fn from_str<
     Ok(Machine::new(instructions, stacks))
 }
 
+/// Build `MSCode` from a str with [`from_str`], naming each size
+/// instead of listing them as generic arguments.
+///
+/// The sizes can be any constant expressions, and the error types are
+/// inferred from `parse` and `to_usize`
+///
+/// # Examples
+/// ```
+/// use msc::{build_machine, machine::State};
+///
+/// let mut machine = build_machine! {
+///     source: "s 0 0 5\n.p",
+///     number: i32,
+///     width: 4,
+///     height: 1,
+///     stack_capacity: 1,
+///     stack_width: 1,
+///     stack_height: 1,
+///     parse: &|value| value.parse(),
+///     to_usize: &|value: i32| value.try_into(),
+/// }
+/// .unwrap();
+///
+/// assert!(matches!(machine.step(), None));
+/// assert_eq!(machine.step().and_then(|output| output.value().copied()), Some(5));
+/// assert_eq!(machine.get_state(), State::Running);
+/// ```
+#[macro_export]
+macro_rules! build_machine {
+    {
+        source: $source:expr,
+        number: $number:ty,
+        width: $width:expr,
+        height: $height:expr,
+        stack_capacity: $stack_capacity:expr,
+        stack_width: $stack_width:expr,
+        stack_height: $stack_height:expr,
+        parse: $parse:expr,
+        to_usize: $to_usize:expr $(,)?
+    } => {
+        $crate::build::from_str::<
+            $number,
+            { $width },
+            { $height },
+            { $stack_capacity },
+            { $stack_width },
+            { $stack_height },
+            _,
+            _,
+        >($source, $parse, $to_usize)
+    };
+}
+
 #[cfg(feature = "std")]
 /// Build `MSCode` from a stdin (<span style="color: var(--codeblock-error-hover-color);">REQUIRES STD!</span>)
 ///
@@ -141,9 +214,11 @@ pub fn from_str<
 /// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
 /// - [`Error::InstructionOutOfRange`] - instruction out of width and height set as constants
 /// - [`Error::InvalidNumber`] - failed to parse a number
-/// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
-/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
+/// - [`Error::InvalidCoordinate`] - failed to convert a coordinate number to a `usize`
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is outside of the stack plane
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+/// - [`Error::Io`] - failed to read a line
 pub fn from_stdin<
     N: Number,
     const WIDTH: usize,
@@ -161,18 +236,51 @@ pub fn from_stdin<
     Machine<N, WIDTH, HEIGHT, STACK_CAPACITY, STACK_WIDTH, STACK_HEIGHT>,
     Error<ParseNError, NToUsizeError>,
 > {
-    use std::io::BufRead;
+    from_reader(source.lock(), try_parse_n, try_usize_from_n)
+}
 
+#[cfg(feature = "std")]
+/// Build `MSCode` from a reader (<span style="color: var(--codeblock-error-hover-color);">REQUIRES STD!</span>)
+///
+/// # Errors
+/// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
+/// - [`Error::InstructionOutOfRange`] - instruction out of width and height set as constants
+/// - [`Error::InvalidNumber`] - failed to parse a number
+/// - [`Error::InvalidCoordinate`] - failed to convert a coordinate number to a `usize`
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is outside of the stack plane
+/// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+/// - [`Error::Io`] - failed to read a line
+pub fn from_reader<
+    N: Number,
+    const WIDTH: usize,
+    const HEIGHT: usize,
+    const STACK_CAPACITY: usize,
+    const STACK_WIDTH: usize,
+    const STACK_HEIGHT: usize,
+    ParseNError: Display,
+    NToUsizeError: Display,
+>(
+    source: impl std::io::BufRead,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+    try_usize_from_n: &dyn Fn(N) -> Result<usize, NToUsizeError>,
+) -> Result<
+    Machine<N, WIDTH, HEIGHT, STACK_CAPACITY, STACK_WIDTH, STACK_HEIGHT>,
+    Error<ParseNError, NToUsizeError>,
+> {
     let mut instructions = ArrayPlane::default();
     let mut stacks = ArrayPlane::default();
 
     // The code body line number
     let mut y = 0;
 
-    let mut lines = source.lock().lines();
-    while let Some(Ok(line)) = lines.next() {
+    let mut lines = source.lines().peekable();
+    // Skip the shebang, if there is one
+    lines.next_if(|line| matches!(line, Ok(line) if is_shebang(line)));
+
+    for line in lines {
         parse_line(
-            &line,
+            &line.map_err(Error::Io)?,
             &mut y,
             &mut instructions,
             &mut stacks,
@@ -190,9 +298,10 @@ pub fn from_stdin<
 /// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
 /// - [`Error::InstructionOutOfRange`] - instruction out of width and height set as constants
 /// - [`Error::InvalidNumber`] - failed to parse a number
-/// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
-/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
+/// - [`Error::InvalidCoordinate`] - failed to convert a coordinate number to a `usize`
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is outside of the stack plane
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
 pub fn parse_line<
     N: Number,
     const WIDTH: usize,
@@ -214,6 +323,28 @@ pub fn parse_line<
     // Match the first char of the line
     match chars.next() {
         Some('#') => {}
+        Some('C') => {
+            // Array stacks all have `STACK_CAPACITY`, so capacity lines
+            // are only checked
+            let line = chars.as_str().split('#').next().unwrap_or_default();
+            let mut numbers = line.split_whitespace().map(|number_str| {
+                try_parse_n(number_str)
+                    .map_err(Error::InvalidNumber)
+                    .and_then(|number| try_usize_from_n(number).map_err(Error::InvalidCoordinate))
+            });
+
+            let (Some(x), Some(y), Some(capacity)) =
+                (numbers.next(), numbers.next(), numbers.next())
+            else {
+                return Err(Error::MissingCapacity);
+            };
+            let (x, y) = (x?, y?);
+            capacity?;
+
+            if stacks.get((x, y)).is_none() {
+                return Err(Error::StackPointerOutOfRange((x, y)));
+            }
+        }
         Some('s') => {
             let mut stack: Option<&mut ArrayStack<STACK_CAPACITY, N>> = None;
             let (mut stack_x, mut stack_y) = (None, None);
@@ -266,15 +397,20 @@ pub fn parse_line<
             }
         }
         Some(char) => {
-            // Parse each character as an instruction and add it to the plane
+            // Parse each character as an instruction and add it to the plane.
+            // Lines are read left to right, with the first character of
+            // the line (re-attached here) in column 0
             for (x, new_instruction) in once(char)
                 .chain(chars)
                 .map(Instruction::try_from)
                 .enumerate()
             {
                 let Some(instruction) = instructions.get_mut((x, *y)) else {
-                        return Err(Error::InstructionOutOfRange((x + 1, *y), new_instruction?.into()));
-                    };
+                    return Err(Error::InstructionOutOfRange(
+                        (x, *y),
+                        new_instruction?.into(),
+                    ));
+                };
 
                 *instruction = new_instruction?;
             }
@@ -288,3 +424,166 @@ pub fn parse_line<
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use core::num::{ParseIntError, TryFromIntError};
+
+    use crate::machine::State;
+
+    use super::{from_str, Error, Machine};
+
+    type TestMachine = Machine<i32, 4, 2, 2, 1, 1>;
+
+    fn build(source: &str) -> Result<TestMachine, Error<ParseIntError, TryFromIntError>> {
+        from_str(source, &|value| value.parse(), &|value: i32| {
+            value.try_into()
+        })
+    }
+
+    #[test]
+    fn instruction_out_of_range_column() {
+        let Err(err) = build(">  v\n^ <p+") else {
+            panic!("Built a machine with an instruction out of range!");
+        };
+
+        assert!(
+            matches!(err, Error::InstructionOutOfRange((4, 1), '+')),
+            "Unexpected error: {err:?}"
+        );
+    }
+
+    #[test]
+    fn instruction_out_of_range_row() {
+        let Err(err) = build(">\n\nv") else {
+            panic!("Built a machine with an instruction out of range!");
+        };
+
+        assert!(
+            matches!(err, Error::InstructionOutOfRange((0, 2), 'v')),
+            "Unexpected error: {err:?}"
+        );
+    }
+
+    fn build_i128(source: &str) -> Result<TestMachine128, Error<ParseIntError, TryFromIntError>> {
+        from_str(source, &|value| value.parse(), &|value: i128| {
+            value.try_into()
+        })
+    }
+
+    type TestMachine128 = Machine<i128, 4, 2, 2, 1, 1>;
+
+    #[test]
+    fn coordinate_out_of_stack_plane() {
+        // Fits in a usize, but the stack plane is 1 x 1
+        let Err(err) = build_i128("s 0 1 5") else {
+            panic!("Built a machine with a stack out of range!");
+        };
+
+        assert!(
+            matches!(err, Error::StackPointerOutOfRange((0, 1))),
+            "Unexpected error: {err:?}"
+        );
+    }
+
+    #[test]
+    fn coordinate_too_large_for_usize() {
+        let Err(err) = build_i128("s 0 18446744073709551616 5") else {
+            panic!("Built a machine with a stack out of range!");
+        };
+
+        assert!(
+            matches!(err, Error::InvalidCoordinate(_)),
+            "Unexpected error: {err:?}"
+        );
+    }
+
+    #[test]
+    fn coordinate_negative() {
+        let Err(err) = build_i128("s -1 0 5") else {
+            panic!("Built a machine with a stack out of range!");
+        };
+
+        assert!(
+            matches!(err, Error::InvalidCoordinate(_)),
+            "Unexpected error: {err:?}"
+        );
+    }
+
+    #[test]
+    fn capacity_line() {
+        // Capacity lines are checked, but do not change the machine
+        assert!(build("C 0 0 8\n.p").is_ok());
+
+        assert!(matches!(build("C 0 0"), Err(Error::MissingCapacity)));
+        assert!(matches!(
+            build("C 0 -1 8"),
+            Err(Error::InvalidCoordinate(_))
+        ));
+        assert!(matches!(
+            build("C 0 1 8"),
+            Err(Error::StackPointerOutOfRange((0, 1)))
+        ));
+    }
+
+    #[test]
+    fn shebang() {
+        let mut machine = build("#!/usr/bin/env msc\ns 0 0 5\n.p").unwrap();
+
+        let mut outputs = [None; 2];
+        let mut i = 0;
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.step() {
+                outputs[i] = output.value().copied();
+                i += 1;
+            }
+        }
+
+        assert_eq!(outputs, [Some(5), None]);
+    }
+
+    #[test]
+    fn build_machine_macro() {
+        let source = "s 0 0 5\n>.pv\n^  <";
+        let parse = |value: &str| value.parse::<i32>();
+        let to_usize = |value: i32| usize::try_from(value);
+
+        let mut machine = crate::build_machine! {
+            source: source,
+            number: i32,
+            width: 4,
+            height: 2,
+            stack_capacity: 2,
+            stack_width: 1,
+            stack_height: 1,
+            parse: &parse,
+            to_usize: &to_usize,
+        }
+        .unwrap();
+        let mut expected: TestMachine =
+            from_str::<i32, 4, 2, 2, 1, 1, ParseIntError, TryFromIntError>(
+                source, &parse, &to_usize,
+            )
+            .unwrap();
+
+        for _ in 0..8 {
+            assert_eq!(machine.snapshot(), expected.snapshot());
+            assert_eq!(
+                machine.step().and_then(|output| output.value().copied()),
+                expected.step().and_then(|output| output.value().copied())
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reader_invalid_utf8() {
+        let result: Result<TestMachine, _> = super::from_reader(
+            &b".p\n\xff\n"[..],
+            &|value| value.parse::<i32>(),
+            &|value: i32| value.try_into(),
+        );
+
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
@@ -2,15 +2,39 @@
 
 use core::cmp::Ordering;
 
-use crate::{stack::Stack, Number, Velocity};
+use crate::{
+    instruction::{self, Instruction},
+    stack::Stack,
+    Number, Velocity,
+};
 
-#[derive(Clone, Copy)]
+/// An instruction that turns the pointer depending on how the register
+/// compares to another value
+///
+/// # Examples
+/// The zero comparator lets the pointer continue when the register is
+/// zero, printing twice, and turns it down when it is negative, printing
+/// once
+/// ```
+/// use msc::{load, machine::State};
+///
+/// let run = |register| {
+///     let source = format!("s 0 0 {register}\n.z pp\n p");
+///     let mut machine = load::from_str(&source, &|value| value.parse::<i32>()).unwrap();
+///     machine.run_collect()
+/// };
+///
+/// assert_eq!(run(0), (vec![0, 0], State::Stopped));
+/// assert_eq!(run(-1), (vec![-1], State::Stopped));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Comparator {
     Zero,
     Stack,
 }
 
 impl Comparator {
+    #[inline]
     pub fn apply<N: Number, StackType: Stack<Item = N>>(
         self,
         register: &N,
@@ -20,19 +44,55 @@ impl Comparator {
         use Comparator::{Stack, Zero};
         match self {
             // Compare register with 0
-            Zero => match register.cmp(&N::ZERO) {
-                Ordering::Equal => velocity,
-                Ordering::Less => velocity ^ 0b10 ^ ((velocity >> 1) & 0b01),
-                Ordering::Greater => velocity ^ 0b11 ^ ((velocity >> 1) & 0b01),
-            },
+            Zero => Self::turn(velocity, register.compare(&N::ZERO)),
             // Compare register with the top of the underlying stack
-            Stack => match register.cmp(&stack.pop().unwrap_or_default()) {
-                Ordering::Equal => velocity,
-                Ordering::Less => velocity ^ 0b10 ^ ((velocity >> 1) & 0b01),
-                Ordering::Greater => velocity ^ 0b11 ^ ((velocity >> 1) & 0b01),
-            },
+            Stack => Self::turn(velocity, register.compare(&stack.pop().unwrap_or_default())),
         }
     }
+
+    /// Get the number of values that the comparator pops off of the stack
+    #[must_use]
+    pub const fn pop_count(self) -> usize {
+        match self {
+            Self::Zero => 0,
+            Self::Stack => 1,
+        }
+    }
+
+    /// Get the new velocity of a pointer after a comparison.
+    /// It continues if equal, and turns one way if less and the
+    /// other way if greater
+    #[must_use]
+    #[inline]
+    pub const fn turn(velocity: Velocity, ordering: Ordering) -> Velocity {
+        match ordering {
+            Ordering::Equal => velocity,
+            Ordering::Less => velocity ^ 0b10 ^ ((velocity >> 1) & 0b01),
+            Ordering::Greater => velocity ^ 0b11 ^ ((velocity >> 1) & 0b01),
+        }
+    }
+
+    /// Get the name of the comparator, which is the name of its variant
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        use Comparator::{Stack, Zero};
+        match self {
+            Zero => "Zero",
+            Stack => "Stack",
+        }
+    }
+
+    /// Get the comparator with a [name](Comparator::name), returning `None` if
+    /// there is none
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        instruction::ALL
+            .iter()
+            .find_map(|&instruction| match instruction {
+                Instruction::Comparator(value) if value.name() == name => Some(value),
+                _ => None,
+            })
+    }
 }
 
 impl From<Comparator> for char {
@@ -47,10 +107,69 @@ impl From<Comparator> for char {
 
 #[cfg(test)]
 mod test {
-    use crate::stack::test_stacks::{FakeStack, SinglePopStack};
+    use core::{
+        cmp::Ordering,
+        ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Sub},
+    };
+
+    use crate::{
+        stack::test_stacks::{FakeStack, SinglePopStack},
+        Number,
+    };
 
     use super::Comparator;
 
+    /// A number that compares in reverse for the comparators
+    #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Reversed(i32);
+
+    macro_rules! reversed_op {
+        ( $trait:ident, $fn:ident ) => {
+            impl $trait for Reversed {
+                type Output = Self;
+
+                fn $fn(self, rhs: Self) -> Self {
+                    Self(self.0.$fn(rhs.0))
+                }
+            }
+        };
+    }
+
+    reversed_op!(Add, add);
+    reversed_op!(Sub, sub);
+    reversed_op!(Mul, mul);
+    reversed_op!(Div, div);
+    reversed_op!(BitOr, bitor);
+    reversed_op!(BitAnd, bitand);
+    reversed_op!(BitXor, bitxor);
+
+    impl Not for Reversed {
+        type Output = Self;
+
+        fn not(self) -> Self {
+            Self(!self.0)
+        }
+    }
+
+    impl Number for Reversed {
+        const ZERO: Self = Self(0);
+        const ONE: Self = Self(1);
+        const MIN: Self = Self(i32::MIN);
+        const MAX: Self = Self(i32::MAX);
+
+        fn to_usize(self) -> Option<usize> {
+            self.0.to_usize()
+        }
+
+        fn from_usize(value: usize) -> Option<Self> {
+            i32::from_usize(value).map(Self)
+        }
+
+        fn compare(&self, other: &Self) -> Ordering {
+            other.0.cmp(&self.0)
+        }
+    }
+
     macro_rules! comp_test {
         ( $name:ident, $comp:path, $reg:expr, $stack:expr, $(( $test:literal, $expected:literal )),* , ) => {
             #[test]
@@ -141,4 +260,42 @@ mod test {
         (0b10, 0b00), // Down -> Right
         (0b11, 0b01), // Up -> Left
     );
+
+    // Test that a custom comparison changes the redirection
+    comp_test!(
+        zero_custom_compare,
+        Comparator::Zero,
+        Reversed(-1),
+        FakeStack::new(),
+        (0b00, 0b11), // Right -> Up
+        (0b01, 0b10), // Left -> Down
+        (0b10, 0b00), // Down -> Right
+        (0b11, 0b01), // Up -> Left
+    );
+
+    comp_test!(
+        stack_custom_compare,
+        Comparator::Stack,
+        Reversed(8),
+        SinglePopStack::new(Reversed(5)),
+        (0b00, 0b10), // Right -> Down
+        (0b01, 0b11), // Left -> Up
+        (0b10, 0b01), // Down -> Left
+        (0b11, 0b00), // Up -> Right
+    );
+
+    #[test]
+    fn custom_number_default_from_u128() {
+        assert_eq!(Reversed::from_u128_wrapping(5), Reversed(5));
+        // The low bits are reduced to fit from 0 to `i32::MAX`
+        assert_eq!(Reversed::from_u128_wrapping(u128::MAX), Reversed(i32::MAX));
+        assert_eq!(Reversed::from_u128_wrapping(1 << 31), Reversed(0));
+    }
+
+    #[test]
+    fn custom_number_default_abs() {
+        for (value, expected) in [(-3, 3), (0, 0), (4, 4)] {
+            assert_eq!(Reversed(value).abs(), Reversed(expected));
+        }
+    }
 }
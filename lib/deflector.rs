@@ -1,8 +1,23 @@
 //! `MSCode` instructions for changing direction
 
-use crate::Velocity;
+use crate::{
+    instruction::{self, Instruction},
+    Number, Velocity,
+};
 
-#[derive(Clone, Copy)]
+/// An instruction that changes the direction of the pointer
+///
+/// # Examples
+/// Turn down at the arrow, so the print at the end of the first line
+/// is skipped
+/// ```
+/// use msc::{load, machine::State};
+///
+/// let mut machine = load::from_str("s 0 0 7\n.  v p\n   p", &|value| value.parse::<i32>()).unwrap();
+///
+/// assert_eq!(machine.run_collect(), (vec![7], State::Stopped));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Deflector {
     RightArrow,
     LeftArrow,
@@ -11,13 +26,20 @@ pub enum Deflector {
     OmniMirror,
     ForwardMirror,
     BackMirror,
+    ConditionalMirror,
 }
 
 impl Deflector {
+    /// Get the new velocity of a pointer moving through the deflector.
+    /// A [`ConditionalMirror`](Deflector::ConditionalMirror) is treated
+    /// as if the register is zero, use [`Deflector::apply_conditional`]
+    /// to give the register
     #[must_use]
+    #[inline]
     pub const fn apply(self, velocity: Velocity) -> Velocity {
         use Deflector::{
-            BackMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror, RightArrow, UpArrow,
+            BackMirror, ConditionalMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror,
+            RightArrow, UpArrow,
         };
         match self {
             RightArrow => 0b00,
@@ -27,14 +49,60 @@ impl Deflector {
             OmniMirror => velocity ^ 0b01,
             BackMirror => velocity ^ 0b10,
             ForwardMirror => velocity ^ 0b11,
+            ConditionalMirror => velocity,
         }
     }
+
+    /// Get the new velocity of a pointer moving through the deflector,
+    /// with its register. A [`ConditionalMirror`](Deflector::ConditionalMirror)
+    /// acts as an [`OmniMirror`](Deflector::OmniMirror) if the register is
+    /// not zero
+    #[must_use]
+    #[inline]
+    pub fn apply_conditional<N: Number>(self, velocity: Velocity, register: &N) -> Velocity {
+        match self {
+            Self::ConditionalMirror if *register != N::ZERO => Self::OmniMirror.apply(velocity),
+            _ => self.apply(velocity),
+        }
+    }
+
+    /// Get the name of the deflector, which is the name of its variant
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        use Deflector::{
+            BackMirror, ConditionalMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror,
+            RightArrow, UpArrow,
+        };
+        match self {
+            RightArrow => "RightArrow",
+            LeftArrow => "LeftArrow",
+            UpArrow => "UpArrow",
+            DownArrow => "DownArrow",
+            OmniMirror => "OmniMirror",
+            ForwardMirror => "ForwardMirror",
+            BackMirror => "BackMirror",
+            ConditionalMirror => "ConditionalMirror",
+        }
+    }
+
+    /// Get the deflector with a [name](Deflector::name), returning `None` if
+    /// there is none
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        instruction::ALL
+            .iter()
+            .find_map(|&instruction| match instruction {
+                Instruction::Deflector(value) if value.name() == name => Some(value),
+                _ => None,
+            })
+    }
 }
 
 impl From<Deflector> for char {
     fn from(val: Deflector) -> Self {
         use Deflector::{
-            BackMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror, RightArrow, UpArrow,
+            BackMirror, ConditionalMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror,
+            RightArrow, UpArrow,
         };
         match val {
             RightArrow => '>',
@@ -44,6 +112,7 @@ impl From<Deflector> for char {
             OmniMirror => 'o',
             ForwardMirror => '/',
             BackMirror => '\\',
+            ConditionalMirror => 'O',
         }
     }
 }
@@ -119,4 +188,35 @@ mod test {
         (0b10, 0b00), // Down -> Right
         (0b11, 0b01), // Up -> Left
     );
+
+    #[test]
+    fn mirror_conditional() {
+        for velocity in 0..4 {
+            // Passes straight through when the register is zero
+            assert_eq!(
+                Deflector::ConditionalMirror.apply_conditional(velocity, &0),
+                velocity
+            );
+            assert_eq!(Deflector::ConditionalMirror.apply(velocity), velocity);
+
+            // Reverses like the omni mirror otherwise
+            for register in [1, -1, i32::MAX] {
+                assert_eq!(
+                    Deflector::ConditionalMirror.apply_conditional(velocity, &register),
+                    velocity ^ 0b01
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn apply_conditional_unconditional() {
+        // Other deflectors do not use the register
+        for velocity in 0..4 {
+            assert_eq!(
+                Deflector::BackMirror.apply_conditional(velocity, &5),
+                Deflector::BackMirror.apply(velocity)
+            );
+        }
+    }
 }
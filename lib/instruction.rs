@@ -1,6 +1,6 @@
 //! `MSCode` instructions
 
-use core::fmt::Display;
+use core::{fmt::Display, str::FromStr};
 
 #[cfg(feature = "std")]
 use std::error::Error;
@@ -12,7 +12,7 @@ use crate::{
     operator::{self, Operator},
 };
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub enum Instruction {
     #[default]
     Space,
@@ -22,10 +22,94 @@ pub enum Instruction {
     IO(IO),
 }
 
+/// Every instruction, in the order they are documented
+pub const ALL: &[Instruction] = {
+    use comparator::Comparator::{Stack, Zero};
+    use deflector::Deflector::{
+        BackMirror, ConditionalMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror, RightArrow,
+        UpArrow,
+    };
+    use io::IO::{Flush, Input, InputAll, InputChar, Print, PrintChar, PrintErr};
+    use operator::Operator::{
+        Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+        LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY, PushZero,
+        Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract, StoreFlag,
+        StoreRegister, Subtract, Tuck, Xor,
+    };
+    use Instruction::{Comparator, Deflector, Operator, Space, IO};
+    &[
+        Space,
+        Deflector(RightArrow),
+        Deflector(LeftArrow),
+        Deflector(UpArrow),
+        Deflector(DownArrow),
+        Deflector(OmniMirror),
+        Deflector(ForwardMirror),
+        Deflector(BackMirror),
+        Deflector(ConditionalMirror),
+        Operator(Push),
+        Operator(PushZero),
+        Operator(Pop),
+        Operator(Duplicate),
+        Operator(DuplicateN),
+        Operator(Tuck),
+        Operator(Add),
+        Operator(Subtract),
+        Operator(Multiply),
+        Operator(Divide),
+        Operator(StackAdd),
+        Operator(StackSubtract),
+        Operator(StackMultiply),
+        Operator(Not),
+        Operator(Or),
+        Operator(And),
+        Operator(Xor),
+        Operator(Jump),
+        Operator(StoreFlag),
+        Operator(LoadFlag),
+        Operator(PushX),
+        Operator(PushY),
+        Operator(PopIfZero),
+        Operator(Abs),
+        Operator(Reverse),
+        Operator(SelectRegister(0)),
+        Operator(SelectRegister(1)),
+        Operator(SelectRegister(2)),
+        Operator(SelectRegister(3)),
+        Operator(StoreRegister),
+        Operator(LoadRegister),
+        Operator(Random),
+        Operator(Less),
+        Operator(Equal),
+        Operator(Greater),
+        Operator(Min),
+        Operator(Max),
+        Comparator(Zero),
+        Comparator(Stack),
+        IO(Print),
+        IO(PrintChar),
+        IO(PrintErr),
+        IO(Input),
+        IO(InputChar),
+        IO(InputAll),
+        IO(Flush),
+    ]
+};
+
+/// Iterate over every instruction character, paired with its instruction
+pub fn all() -> impl Iterator<Item = (char, Instruction)> {
+    ALL.iter()
+        .map(|&instruction| (instruction.into(), instruction))
+}
+
 #[derive(Clone, Debug)]
 pub enum IntoInstructionError {
     /// Character does not match any instructions
     UnknownChar(char),
+    /// Empty string, where one character was expected
+    Empty,
+    /// String with more than one character
+    MultipleChars,
 }
 
 #[cfg(feature = "std")]
@@ -33,9 +117,17 @@ impl Error for IntoInstructionError {}
 
 impl Display for IntoInstructionError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        use IntoInstructionError::UnknownChar;
+        use IntoInstructionError::{Empty, MultipleChars, UnknownChar};
         match self {
-            UnknownChar(char) => write!(f, "unknown instruction: {char}"),
+            UnknownChar(char) => {
+                write!(
+                    f,
+                    "unknown instruction: {char:?} (U+{:04X})",
+                    u32::from(*char)
+                )
+            }
+            Empty => write!(f, "empty instruction, expected one character"),
+            MultipleChars => write!(f, "more than one instruction character"),
         }
     }
 }
@@ -46,11 +138,15 @@ impl TryFrom<char> for Instruction {
     fn try_from(value: char) -> Result<Self, Self::Error> {
         use comparator::Comparator::{Stack, Zero};
         use deflector::Deflector::{
-            BackMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror, RightArrow, UpArrow,
+            BackMirror, ConditionalMirror, DownArrow, ForwardMirror, LeftArrow, OmniMirror,
+            RightArrow, UpArrow,
         };
-        use io::IO::{Input, Print};
+        use io::IO::{Flush, Input, InputAll, InputChar, Print, PrintChar, PrintErr};
         use operator::Operator::{
-            Add, And, Divide, Duplicate, Multiply, Not, Or, Pop, Push, Subtract, Xor,
+            Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+            LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY,
+            PushZero, Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract,
+            StoreFlag, StoreRegister, Subtract, Tuck, Xor,
         };
         use Instruction::{Comparator, Deflector, Operator, Space, IO};
         match value {
@@ -63,10 +159,14 @@ impl TryFrom<char> for Instruction {
             'o' => Ok(Deflector(OmniMirror)),
             '\\' => Ok(Deflector(BackMirror)),
             '/' => Ok(Deflector(ForwardMirror)),
+            'O' => Ok(Deflector(ConditionalMirror)),
 
             ',' => Ok(Operator(Push)),
+            ';' => Ok(Operator(PushZero)),
             '.' => Ok(Operator(Pop)),
             'd' => Ok(Operator(Duplicate)),
+            'D' => Ok(Operator(DuplicateN)),
+            't' => Ok(Operator(Tuck)),
             '+' => Ok(Operator(Add)),
             '-' => Ok(Operator(Subtract)),
             '*' => Ok(Operator(Multiply)),
@@ -75,18 +175,84 @@ impl TryFrom<char> for Instruction {
             '|' => Ok(Operator(Or)),
             '&' => Ok(Operator(And)),
             ':' => Ok(Operator(Xor)),
+            'A' => Ok(Operator(StackAdd)),
+            'S' => Ok(Operator(StackSubtract)),
+            'T' => Ok(Operator(StackMultiply)),
+            'j' => Ok(Operator(Jump)),
+            'f' => Ok(Operator(StoreFlag)),
+            'F' => Ok(Operator(LoadFlag)),
+            'x' => Ok(Operator(PushX)),
+            'y' => Ok(Operator(PushY)),
+            '?' => Ok(Operator(PopIfZero)),
+            'a' => Ok(Operator(Abs)),
+            'r' => Ok(Operator(Reverse)),
+            '0' => Ok(Operator(SelectRegister(0))),
+            '1' => Ok(Operator(SelectRegister(1))),
+            '2' => Ok(Operator(SelectRegister(2))),
+            '3' => Ok(Operator(SelectRegister(3))),
+            '[' => Ok(Operator(StoreRegister)),
+            ']' => Ok(Operator(LoadRegister)),
+            '%' => Ok(Operator(Random)),
+            'L' => Ok(Operator(Less)),
+            'E' => Ok(Operator(Equal)),
+            'G' => Ok(Operator(Greater)),
+            'm' => Ok(Operator(Min)),
+            'M' => Ok(Operator(Max)),
 
             'z' => Ok(Comparator(Zero)),
             'c' => Ok(Comparator(Stack)),
 
             'p' => Ok(IO(Print)),
+            'P' => Ok(IO(PrintChar)),
+            'e' => Ok(IO(PrintErr)),
             'i' => Ok(IO(Input)),
+            'I' => Ok(IO(InputChar)),
+            '@' => Ok(IO(InputAll)),
+            'w' => Ok(IO(Flush)),
 
             _ => Err(IntoInstructionError::UnknownChar(value)),
         }
     }
 }
 
+impl Instruction {
+    /// Get the name of the instruction, which is `"Space"` or the name
+    /// of the instruction's variant, such as `"RightArrow"`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Space => "Space",
+            Self::Deflector(deflector) => deflector.name(),
+            Self::Operator(operation) => operation.name(),
+            Self::Comparator(comparator) => comparator.name(),
+            Self::IO(io) => io.name(),
+        }
+    }
+
+    /// Get the instruction with a [name](Instruction::name), returning
+    /// `None` if there is none
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL.iter()
+            .copied()
+            .find(|instruction| instruction.name() == name)
+    }
+}
+
+/// Parse a string of exactly one instruction character
+impl FromStr for Instruction {
+    type Err = IntoInstructionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(char), None) => char.try_into(),
+            (None, _) => Err(IntoInstructionError::Empty),
+            (Some(_), Some(_)) => Err(IntoInstructionError::MultipleChars),
+        }
+    }
+}
+
 impl From<Instruction> for char {
     fn from(val: Instruction) -> Self {
         use Instruction::{Comparator, Deflector, Operator, Space, IO};
@@ -99,3 +265,97 @@ impl From<Instruction> for char {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "std")]
+    use super::IntoInstructionError;
+    use super::{all, Instruction, ALL};
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn unknown_char_display() {
+        let message = |char| IntoInstructionError::UnknownChar(char).to_string();
+
+        assert_eq!(message('\t'), "unknown instruction: '\\t' (U+0009)");
+        assert_eq!(message('q'), "unknown instruction: 'q' (U+0071)");
+        assert_eq!(
+            message('\u{1F600}'),
+            "unknown instruction: '\u{1F600}' (U+1F600)"
+        );
+    }
+
+    #[test]
+    fn from_str() {
+        use super::IntoInstructionError::{Empty, MultipleChars, UnknownChar};
+
+        assert_eq!("+".parse::<Instruction>().map(char::from).ok(), Some('+'));
+        assert!(matches!("".parse::<Instruction>(), Err(Empty)));
+        assert!(matches!("++".parse::<Instruction>(), Err(MultipleChars)));
+        assert!(matches!("q".parse::<Instruction>(), Err(UnknownChar('q'))));
+    }
+
+    #[test]
+    fn name_round_trip() {
+        use super::Instruction::{Comparator, Deflector, Operator, Space, IO};
+
+        for &instruction in ALL {
+            let name = instruction.name();
+            let from_name = Instruction::from_name(name).map(char::from);
+            assert_eq!(from_name, Some(char::from(instruction)), "{name}");
+
+            let variant = match instruction {
+                Space => Some(' '),
+                Deflector(_) => super::Deflector::from_name(name).map(char::from),
+                Operator(_) => super::Operator::from_name(name).map(char::from),
+                Comparator(_) => super::Comparator::from_name(name).map(char::from),
+                IO(_) => super::IO::from_name(name).map(char::from),
+            };
+            assert_eq!(variant, Some(char::from(instruction)), "{name}");
+        }
+    }
+
+    #[test]
+    fn name_examples() {
+        assert_eq!(
+            Instruction::from_name("RightArrow").map(char::from),
+            Some('>')
+        );
+        assert_eq!(super::Operator::from_name("Add").map(char::from), Some('+'));
+        assert_eq!(super::IO::from_name("Print").map(char::from), Some('p'));
+    }
+
+    #[test]
+    fn name_unknown() {
+        assert!(Instruction::from_name("").is_none());
+        assert!(Instruction::from_name("rightarrow").is_none());
+        // Names are only found for their own type
+        assert!(super::Deflector::from_name("Add").is_none());
+        assert!(super::Operator::from_name("RightArrow").is_none());
+        assert!(super::Comparator::from_name("Space").is_none());
+        assert!(super::IO::from_name("Zero").is_none());
+    }
+
+    #[test]
+    fn all_round_trip() {
+        for (char, instruction) in all() {
+            let parsed = Instruction::try_from(char).unwrap();
+            assert_eq!(char::from(parsed), char);
+            assert_eq!(char::from(instruction), char);
+        }
+    }
+
+    #[test]
+    fn all_complete() {
+        // Every character that parses as an instruction is in the list
+        let parsed = (char::MIN..=char::MAX)
+            .filter(|&char| Instruction::try_from(char).is_ok())
+            .count();
+        assert_eq!(parsed, ALL.len());
+
+        // and there are no duplicates
+        for (i, (char, _)) in all().enumerate() {
+            assert!(!all().skip(i + 1).any(|(other, _)| other == char));
+        }
+    }
+}
@@ -1,49 +1,693 @@
 //! `MSCode` instructions for input and output
 
-#[derive(Clone, Copy)]
+use core::fmt::Display;
+
+use crate::{
+    instruction::{self, Instruction},
+    Number,
+};
+
+/// An instruction for input or output
+///
+/// # Examples
+/// Read a number, double it and print it
+/// ```
+/// use msc::{load, machine::State};
+///
+/// let mut machine = load::from_str("i,+p", &|value| value.parse::<i32>()).unwrap();
+///
+/// assert_eq!(machine.run_collect(), (vec![], State::InputWaiting));
+/// machine.input(21);
+/// assert_eq!(machine.run_collect(), (vec![42], State::Stopped));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum IO {
     Print,
+    PrintChar,
+    PrintErr,
     Input,
+    InputChar,
+    InputAll,
+    Flush,
+}
+
+/// The kind of value that an input instruction is waiting for
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum InputKind {
+    /// A number, from [`Input`](IO::Input)
+    #[default]
+    Number,
+    /// The code point of a character, from [`InputChar`](IO::InputChar)
+    Char,
+    /// Every number that is available, from [`InputAll`](IO::InputAll),
+    /// which are pushed onto the stack instead of setting the register
+    All,
 }
 
 impl IO {
-    pub const fn apply<N>(self, register: &N) -> (Option<&N>, bool) {
-        use IO::{Input, Print};
+    /// Get the value to output, if any, and the kind of input to read, if any.
+    /// [`Flush`](IO::Flush) outputs nothing, but the machine outputs
+    /// [`Output::Flush`] for it
+    #[inline]
+    pub const fn apply<N>(self, register: &N) -> (Option<&N>, Option<InputKind>) {
+        use IO::{Flush, Input, InputAll, InputChar, Print, PrintChar, PrintErr};
+        match self {
+            Print | PrintChar | PrintErr => (Some(register), None),
+            Flush => (None, None),
+            Input => (None, Some(InputKind::Number)),
+            InputChar => (None, Some(InputKind::Char)),
+            InputAll => (None, Some(InputKind::All)),
+        }
+    }
+
+    /// Get the name of the IO instruction, which is the name of its variant
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        use IO::{Flush, Input, InputAll, InputChar, Print, PrintChar, PrintErr};
+        match self {
+            Print => "Print",
+            PrintChar => "PrintChar",
+            PrintErr => "PrintErr",
+            Input => "Input",
+            InputChar => "InputChar",
+            InputAll => "InputAll",
+            Flush => "Flush",
+        }
+    }
+
+    /// Get the IO instruction with a [name](IO::name), returning `None` if
+    /// there is none
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        instruction::ALL
+            .iter()
+            .find_map(|&instruction| match instruction {
+                Instruction::IO(value) if value.name() == name => Some(value),
+                _ => None,
+            })
+    }
+}
+
+/// A value output by the machine
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Output<'a, N> {
+    /// A value from [`Print`](IO::Print), to be shown as a number
+    Number(&'a N),
+    /// A value from [`PrintChar`](IO::PrintChar), to be shown as a character
+    Char(&'a N),
+    /// A value from [`PrintErr`](IO::PrintErr), to be shown as a number
+    /// on the [`Error`](Channel::Error) channel
+    Error(&'a N),
+    /// A request from [`Flush`](IO::Flush) to write any buffered outputs
+    Flush,
+}
+
+/// The channel that an [`Output`] is sent on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    /// The program's output, such as stdout
+    Primary,
+    /// A separate output for errors and debugging, such as stderr
+    Error,
+}
+
+impl<'a, N> Output<'a, N> {
+    /// Get the output value, or `None` for [`Output::Flush`]
+    #[must_use]
+    pub const fn value(self) -> Option<&'a N> {
         match self {
-            Print => (Some(register), false),
-            Input => (None, true),
+            Self::Number(value) | Self::Char(value) | Self::Error(value) => Some(value),
+            Self::Flush => None,
+        }
+    }
+
+    /// Get the channel that the output is sent on.
+    /// [`Output::Flush`] is sent on the [`Primary`](Channel::Primary)
+    /// channel, but applies to both
+    #[must_use]
+    pub const fn channel(self) -> Channel {
+        match self {
+            Self::Number(_) | Self::Char(_) | Self::Flush => Channel::Primary,
+            Self::Error(_) => Channel::Error,
+        }
+    }
+}
+
+/// Displays numbers as numbers and characters as characters.
+/// Values that are not valid Unicode scalar values are displayed
+/// as U+FFFD (the replacement character), and [`Output::Flush`]
+/// displays nothing
+impl<N: Number + Display> Display for Output<'_, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Number(value) | Self::Error(value) => value.fmt(f),
+            Self::Char(&value) => n_to_char(value)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .fmt(f),
+            Self::Flush => Ok(()),
+        }
+    }
+}
+
+/// Convert a number to a character, returning `None` if it is not
+/// a valid Unicode scalar value (negative, a surrogate or too large)
+pub fn n_to_char<N: Number>(value: N) -> Option<char> {
+    let value = u32::try_from(value.to_usize()?).ok()?;
+    char::from_u32(value)
+}
+
+/// Convert a character to its code point as a number, returning
+/// `None` if it does not fit
+#[must_use]
+pub fn char_to_n<N: Number>(value: char) -> Option<N> {
+    N::from_usize(usize::try_from(u32::from(value)).ok()?)
+}
+
+/// A source of inputs for the [`Input`](IO::Input),
+/// [`InputChar`](IO::InputChar) and [`InputAll`](IO::InputAll) instructions
+pub trait Input<N> {
+    /// Read the next input, returning `None` if there is none available
+    fn read(&mut self) -> Option<N>;
+
+    /// Read the code point of the next input character, returning
+    /// `None` if there is none available.
+    /// By default, this is the same as [`Input::read`]
+    fn read_char(&mut self) -> Option<N> {
+        self.read()
+    }
+
+    /// Read the next input of the given kind.
+    /// For [`InputKind::All`], this reads one number, so callers
+    /// read the rest with [`Input::read_more`]
+    fn read_kind(&mut self, kind: InputKind) -> Option<N> {
+        match kind {
+            InputKind::Number | InputKind::All => self.read(),
+            InputKind::Char => self.read_char(),
+        }
+    }
+
+    /// Read another input for an [`InputAll`](IO::InputAll) instruction,
+    /// after the first, returning `None` when there are no more for now.
+    /// By default, this is the same as [`Input::read`]
+    fn read_more(&mut self) -> Option<N> {
+        self.read()
+    }
+}
+
+/// An [`Input`] implementation that calls a function for each input,
+/// so it never runs out. An [`InputAll`](IO::InputAll) instruction
+/// reads a single input from it
+pub struct FnInput<F>(pub F);
+
+impl<N, F: FnMut() -> N> Input<N> for FnInput<F> {
+    fn read(&mut self) -> Option<N> {
+        Some((self.0)())
+    }
+
+    fn read_more(&mut self) -> Option<N> {
+        None
+    }
+}
+
+/// An [`Input`] implementation that reads from a preloaded slice
+pub struct SliceInput<'a, N>(core::slice::Iter<'a, N>);
+
+impl<N: Copy> Input<N> for SliceInput<'_, N> {
+    fn read(&mut self) -> Option<N> {
+        self.0.next().copied()
+    }
+}
+
+impl<'a, N> SliceInput<'a, N> {
+    #[must_use]
+    pub fn new(inputs: &'a [N]) -> Self {
+        Self(inputs.iter())
+    }
+}
+
+#[cfg(feature = "std")]
+pub use std_inputs::*;
+#[cfg(feature = "std")]
+mod std_inputs {
+    use std::{
+        boxed::Box,
+        collections::VecDeque,
+        fmt::Display,
+        io::{stdin, stdout, BufRead, StdinLock, Write},
+        string::{String, ToString},
+    };
+
+    use super::{char_to_n, Input, InputKind};
+    use crate::Number;
+
+    /// Read inputs from the front of the queue
+    impl<N> Input<N> for VecDeque<N> {
+        fn read(&mut self) -> Option<N> {
+            self.pop_front()
+        }
+    }
+
+    /// An [`Input`] implementation that reads lines of
+    /// whitespace-separated numbers from a reader
+    ///
+    /// Each line may hold several numbers, which are used by
+    /// successive reads before another line is read.
+    /// If a number fails to parse, a new line is read to replace
+    /// just that number.
+    ///
+    /// Characters are read one at a time from the same tokens, so
+    /// whitespace cannot be read as a character.
+    ///
+    /// Prompts, and messages about inputs that cannot be used, are
+    /// written to stdout by default; see [`LineInput::with_prompt`] and
+    /// [`LineInput::with_prompt_writer`].
+    pub struct LineInput<'a, N, ParseNError: Display, R: BufRead> {
+        try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>,
+        prompt: bool,
+        prompt_text: Option<String>,
+        prompt_writer: Box<dyn Write + 'a>,
+        reader: R,
+        pending: VecDeque<String>,
+    }
+
+    /// A [`LineInput`] that reads from stdin
+    pub type StdinInput<'a, N, ParseNError> = LineInput<'a, N, ParseNError, StdinLock<'static>>;
+
+    impl<N: Number, ParseNError: Display, R: BufRead> Input<N> for LineInput<'_, N, ParseNError, R> {
+        fn read(&mut self) -> Option<N> {
+            loop {
+                let Some(token) = self.pending.pop_front() else {
+                    self.read_line(InputKind::Number)?;
+                    continue;
+                };
+
+                match (self.try_parse_n)(&token) {
+                    Ok(value) => return Some(value),
+                    Err(err) => {
+                        self.report(format_args!("{token:?}\n{err}"))?;
+                        self.read_line(InputKind::Number)?;
+                    }
+                }
+            }
+        }
+
+        fn read_char(&mut self) -> Option<N> {
+            loop {
+                let Some(token) = self.pending.pop_front() else {
+                    self.read_line(InputKind::Char)?;
+                    continue;
+                };
+
+                let mut chars = token.chars();
+                let Some(value) = chars.next() else {
+                    continue;
+                };
+                // Keep the rest of the token for the next read
+                if !chars.as_str().is_empty() {
+                    self.pending.push_front(chars.as_str().to_string());
+                }
+
+                match char_to_n(value) {
+                    Some(value) => return Some(value),
+                    None => self.report(format_args!("{value:?} is out of range"))?,
+                }
+            }
+        }
+    }
+
+    impl<'a, N, ParseNError: Display, R: BufRead> LineInput<'a, N, ParseNError, R> {
+        /// Create a new line input from a reader, printing a prompt
+        /// before each line is read if `prompt` is set
+        #[must_use]
+        pub fn from_reader(
+            reader: R,
+            try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>,
+            prompt: bool,
+        ) -> Self {
+            Self {
+                try_parse_n,
+                prompt,
+                prompt_text: None,
+                prompt_writer: Box::new(stdout()),
+                reader,
+                pending: VecDeque::new(),
+            }
+        }
+
+        /// Use `prompt` as the prompt for every kind of input,
+        /// instead of `"> "` for numbers and `"char> "` for characters
+        ///
+        /// This has no effect if prompting is disabled.
+        #[must_use]
+        pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+            self.prompt_text = Some(prompt.into());
+            self
+        }
+
+        /// Write prompts and messages about inputs that cannot be used
+        /// to `writer` instead of stdout
+        #[must_use]
+        pub fn with_prompt_writer(mut self, writer: impl Write + 'a) -> Self {
+            self.prompt_writer = Box::new(writer);
+            self
+        }
+
+        /// Write a message about an input that cannot be used,
+        /// returning `None` if writing fails
+        fn report(&mut self, message: core::fmt::Arguments<'_>) -> Option<()> {
+            writeln!(self.prompt_writer, "{message}").ok()
+        }
+
+        /// Read a line and queue its tokens ahead of any pending tokens,
+        /// prompting for the kind of input that is being read
+        fn read_line(&mut self, kind: InputKind) -> Option<()> {
+            if self.prompt {
+                let prompt = match (&self.prompt_text, kind) {
+                    (Some(prompt), _) => prompt.as_str(),
+                    (None, InputKind::Number | InputKind::All) => "> ",
+                    (None, InputKind::Char) => "char> ",
+                };
+                self.prompt_writer.write_all(prompt.as_bytes()).ok()?;
+                self.prompt_writer.flush().ok()?;
+            }
+
+            let mut buffer = String::new();
+            // Stop when the reader has ended or cannot be read
+            if self.reader.read_line(&mut buffer).ok()? == 0 {
+                return None;
+            }
+
+            for token in buffer.split_whitespace().rev() {
+                self.pending.push_front(token.to_string());
+            }
+
+            Some(())
+        }
+    }
+
+    impl<'a, N, ParseNError: Display> StdinInput<'a, N, ParseNError> {
+        /// Create a new stdin input, printing a prompt before each
+        /// line is read if `prompt` is set
+        #[must_use]
+        pub fn new(try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>, prompt: bool) -> Self {
+            Self::from_reader(stdin().lock(), try_parse_n, prompt)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use std_outputs::*;
+#[cfg(feature = "std")]
+mod std_outputs {
+    use std::{
+        fmt::Display,
+        io::{self, BufWriter, Write},
+    };
+
+    use super::Output;
+    use crate::Number;
+
+    /// Writes outputs to a buffered primary writer and an unbuffered
+    /// error writer. The primary writer is only written to when its
+    /// buffer fills, on [`Output::Flush`] or with [`BufferedOutput::flush`]
+    pub struct BufferedOutput<W: Write, E: Write> {
+        primary: BufWriter<W>,
+        error: E,
+    }
+
+    impl<W: Write, E: Write> BufferedOutput<W, E> {
+        #[must_use]
+        pub fn new(primary: W, error: E) -> Self {
+            Self {
+                primary: BufWriter::new(primary),
+                error,
+            }
+        }
+
+        /// Write an output. Numbers are written on their own lines and
+        /// characters are not separated, so that they can form text
+        ///
+        /// # Errors
+        /// Returns an error if writing or flushing fails
+        pub fn emit<N: Number + Display>(&mut self, output: Output<'_, N>) -> io::Result<()> {
+            match output {
+                Output::Number(_) => writeln!(self.primary, "{output}"),
+                Output::Char(_) => write!(self.primary, "{output}"),
+                Output::Error(_) => writeln!(self.error, "{output}"),
+                Output::Flush => self.flush(),
+            }
+        }
+
+        /// Write any buffered outputs
+        ///
+        /// # Errors
+        /// Returns an error if writing or flushing fails
+        pub fn flush(&mut self) -> io::Result<()> {
+            self.primary.flush()?;
+            self.error.flush()
+        }
+
+        /// Get the primary writer. Buffered outputs have not been
+        /// written to it
+        #[must_use]
+        pub fn primary(&self) -> &W {
+            self.primary.get_ref()
+        }
+
+        /// Get the error writer
+        #[must_use]
+        pub const fn error(&self) -> &E {
+            &self.error
         }
     }
 }
 
 impl From<IO> for char {
     fn from(value: IO) -> Self {
-        use IO::{Input, Print};
+        use IO::{Flush, Input, InputAll, InputChar, Print, PrintChar, PrintErr};
         match value {
             Print => 'p',
+            PrintChar => 'P',
+            PrintErr => 'e',
             Input => 'i',
+            InputChar => 'I',
+            InputAll => '@',
+            Flush => 'w',
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod test {
-    use super::IO;
+    use core::num::Wrapping;
+
+    use super::{n_to_char, Channel, Input, InputKind, Output, SliceInput, IO};
 
     macro_rules! test_io {
-        ( $name:ident, $io_op:path, $reg:literal, $( $reg_match:pat_param )|+ $( if $reg_guard: expr )?, $input_wait:literal ) => {
+        ( $name:ident, $io_op:path, $reg:literal, $( $reg_match:pat_param )|+ $( if $reg_guard: expr )?, $input_kind:expr ) => {
             #[test]
             fn $name() {
                 let register = $reg;
-                let (output, input_wait) = $io_op.apply(&register);
+                let (output, input_kind) = $io_op.apply(&register);
 
                 assert!(matches!(output, $( $reg_match )|+ $( if $reg_guard )?));
-                assert_eq!(input_wait, $input_wait);
+                assert_eq!(input_kind, $input_kind);
             }
         };
     }
 
-    test_io!(print, IO::Print, 5, Some(&new_register) if new_register == 5, false);
+    test_io!(print, IO::Print, 5, Some(&new_register) if new_register == 5, None);
+
+    test_io!(print_char, IO::PrintChar, 65, Some(&new_register) if new_register == 65, None);
+
+    test_io!(print_err, IO::PrintErr, 5, Some(&new_register) if new_register == 5, None);
+
+    test_io!(input, IO::Input, 5, None, Some(InputKind::Number));
+
+    test_io!(input_char, IO::InputChar, 5, None, Some(InputKind::Char));
+
+    test_io!(input_all, IO::InputAll, 5, None, Some(InputKind::All));
+
+    test_io!(flush, IO::Flush, 5, None, None);
+
+    #[test]
+    fn output_channel() {
+        assert_eq!(Output::Number(&1).channel(), Channel::Primary);
+        assert_eq!(Output::Char(&1).channel(), Channel::Primary);
+        assert_eq!(Output::Error(&1).channel(), Channel::Error);
+        assert_eq!(Output::<i32>::Flush.channel(), Channel::Primary);
+    }
+
+    #[test]
+    fn output_value() {
+        assert_eq!(Output::Number(&1).value(), Some(&1));
+        assert_eq!(Output::Error(&2).value(), Some(&2));
+        assert_eq!(Output::<i32>::Flush.value(), None);
+    }
+
+    #[test]
+    fn n_to_char_ascii() {
+        assert_eq!(n_to_char(65), Some('A'));
+        assert_eq!(n_to_char(Wrapping(10_u8)), Some('\n'));
+    }
+
+    #[test]
+    fn n_to_char_surrogate() {
+        assert_eq!(n_to_char(0xD800), None);
+        assert_eq!(n_to_char(0xDFFF), None);
+    }
+
+    #[test]
+    fn n_to_char_negative() {
+        assert_eq!(n_to_char(-65), None);
+    }
+
+    #[test]
+    fn n_to_char_too_large() {
+        assert_eq!(n_to_char(0x11_0000), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn output_display() {
+        assert_eq!(Output::Number(&65).to_string(), "65");
+        assert_eq!(Output::Char(&65).to_string(), "A");
+        assert_eq!(Output::Char(&0xD800).to_string(), "\u{FFFD}");
+        assert_eq!(Output::Char(&-1).to_string(), "\u{FFFD}");
+        assert_eq!(Output::Error(&65).to_string(), "65");
+        assert_eq!(Output::<i32>::Flush.to_string(), "");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn buffered_output() {
+        use super::BufferedOutput;
+
+        let mut output = BufferedOutput::new(Vec::new(), Vec::new());
+        output.emit(Output::Number(&1)).unwrap();
+        output.emit(Output::Char(&65)).unwrap();
+        output.emit(Output::Error(&2)).unwrap();
+
+        // Errors are written straight away
+        assert!(output.primary().is_empty());
+        assert_eq!(output.error(), b"2\n");
+
+        output.emit(Output::<i32>::Flush).unwrap();
+        assert_eq!(output.primary(), b"1\nA");
+    }
+
+    #[test]
+    fn slice_input() {
+        let mut input = SliceInput::new(&[1, 2, 3]);
+
+        assert_eq!(input.read(), Some(1));
+        assert_eq!(input.read(), Some(2));
+        assert_eq!(input.read(), Some(3));
+        assert_eq!(input.read(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut input = LineInput::from_reader(&b"1 2  3\n\n 4\n"[..], &parse, false);
+
+        assert_eq!(input.read(), Some(1));
+        assert_eq!(input.read(), Some(2));
+        assert_eq!(input.read(), Some(3));
+        assert_eq!(input.read(), Some(4));
+        assert_eq!(input.read(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input_invalid_token() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut input = LineInput::from_reader(&b"1 a 3\n2\n"[..], &parse, false);
+
+        assert_eq!(input.read(), Some(1));
+        assert_eq!(input.read(), Some(2));
+        assert_eq!(input.read(), Some(3));
+        assert_eq!(input.read(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input_default_prompt() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut prompts = Vec::new();
+        {
+            let mut input = LineInput::from_reader(&b"1\na\n"[..], &parse, true)
+                .with_prompt_writer(&mut prompts);
 
-    test_io!(input, IO::Input, 5, None, true);
+            assert_eq!(input.read(), Some(1));
+            assert_eq!(input.read_char(), Some('a' as i32));
+        }
+
+        assert_eq!(prompts, b"> char> ");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input_custom_prompt() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut prompts = Vec::new();
+        {
+            let mut input = LineInput::from_reader(&b"1\n2\n"[..], &parse, true)
+                .with_prompt("number? ")
+                .with_prompt_writer(&mut prompts);
+
+            assert_eq!(input.read(), Some(1));
+            assert_eq!(input.read(), Some(2));
+        }
+
+        assert_eq!(prompts, b"number? number? ");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input_errors_to_prompt_writer() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut written = Vec::new();
+        {
+            let mut input = LineInput::from_reader(&b"x\n3\n"[..], &parse, true)
+                .with_prompt_writer(&mut written);
+
+            assert_eq!(input.read(), Some(3));
+        }
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "> \"x\"\ninvalid digit found in string\n> "
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_input_prompt_suppressed() {
+        use super::LineInput;
+
+        let parse = |token: &str| token.parse::<i32>();
+        let mut prompts = Vec::new();
+        {
+            let mut input = LineInput::from_reader(&b"1\n"[..], &parse, false)
+                .with_prompt("number? ")
+                .with_prompt_writer(&mut prompts);
+
+            assert_eq!(input.read(), Some(1));
+        }
+
+        assert!(prompts.is_empty());
+    }
 }
@@ -10,6 +10,9 @@
 )]
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
 mod number;
 pub use number::Number;
 
@@ -20,18 +23,168 @@ pub mod io;
 pub mod machine;
 pub mod operator;
 pub mod plane;
+pub mod program;
+pub mod rng;
 pub mod stack;
 
+#[cfg(feature = "std")]
+pub mod batch;
 #[cfg(feature = "std")]
 pub mod load;
+#[cfg(feature = "std")]
+pub mod pipeline;
 
 pub mod build;
 
 pub type Velocity = u8;
 pub type Pointer = (usize, usize);
 
+/// A direction that the pointer can move in, which is what a
+/// [`Velocity`] means
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Right,
+    Left,
+    Down,
+    Up,
+}
+
+impl Direction {
+    /// Every direction, in the order of their velocities
+    pub const ALL: [Self; 4] = [Self::Right, Self::Left, Self::Down, Self::Up];
+
+    /// Get the direction of a velocity.
+    /// Only the lowest 2 bits are used
+    #[must_use]
+    pub const fn from_velocity_bits(velocity: Velocity) -> Self {
+        match velocity & 0b11 {
+            0b00 => Self::Right,
+            0b01 => Self::Left,
+            0b10 => Self::Down,
+            _ => Self::Up,
+        }
+    }
+
+    /// Get the velocity of the direction
+    #[must_use]
+    pub const fn to_velocity(self) -> Velocity {
+        match self {
+            Self::Right => 0b00,
+            Self::Left => 0b01,
+            Self::Down => 0b10,
+            Self::Up => 0b11,
+        }
+    }
+
+    /// Get the arrow character of the direction
+    #[must_use]
+    pub const fn arrow(self) -> char {
+        match self {
+            Self::Right => '>',
+            Self::Left => '<',
+            Self::Down => 'v',
+            Self::Up => '^',
+        }
+    }
+
+    /// Get the name of the direction
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Right => "Right",
+            Self::Left => "Left",
+            Self::Down => "Down",
+            Self::Up => "Up",
+        }
+    }
+}
+
+impl From<Direction> for Velocity {
+    fn from(value: Direction) -> Self {
+        value.to_velocity()
+    }
+}
+
+/// A velocity with bits set above the lowest 2, which is not a
+/// [`Direction`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidVelocity(pub Velocity);
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidVelocity {}
+
+impl core::fmt::Display for InvalidVelocity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid velocity: {:#010b}", self.0)
+    }
+}
+
+impl TryFrom<Velocity> for Direction {
+    type Error = InvalidVelocity;
+
+    fn try_from(value: Velocity) -> Result<Self, Self::Error> {
+        if value > 0b11 {
+            return Err(InvalidVelocity(value));
+        }
+        Ok(Self::from_velocity_bits(value))
+    }
+}
+
+/// A position in the source code, with a 0-based line and column.
+/// It displays with 1-based numbers.
+///
+/// The column counts chars (Unicode scalar values), not bytes, so it
+/// differs from the byte offset after a non-ASCII character.
+/// Use [`SourcePosition::byte_offset`] to convert it
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourcePosition {
+    /// Get the byte offset of the column in its line, returning `None`
+    /// if the line is too short.
+    /// A column just past the end of the line gives the line's length
+    #[must_use]
+    pub fn byte_offset(&self, line: &str) -> Option<usize> {
+        line.char_indices()
+            .map(|(offset, _)| offset)
+            .chain(core::iter::once(line.len()))
+            .nth(self.column)
+    }
+}
+
+impl core::fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}, column {}", self.line + 1, self.column + 1)
+    }
+}
+
+/// Get the arrow character for the direction of a velocity.
+/// Only the lowest 2 bits are used
+#[must_use]
+pub const fn velocity_to_arrow(velocity: Velocity) -> char {
+    Direction::from_velocity_bits(velocity).arrow()
+}
+
+/// Get the name of the direction of a velocity.
+/// Only the lowest 2 bits are used
 #[must_use]
-pub fn add_velocity_to_pointer(velocity: Velocity, mut pointer: Pointer) -> (usize, usize) {
+pub const fn velocity_name(velocity: Velocity) -> &'static str {
+    Direction::from_velocity_bits(velocity).name()
+}
+
+/// Check if a line is a shebang (`#!...`).
+/// Shebangs are only recognised on the first line of a program
+#[must_use]
+pub const fn is_shebang(line: &str) -> bool {
+    matches!(line.as_bytes(), [b'#', b'!', ..])
+}
+
+#[must_use]
+#[inline]
+pub const fn add_velocity_to_pointer(velocity: Velocity, mut pointer: Pointer) -> (usize, usize) {
     let a = if velocity & 0b10 == 0 {
         &mut pointer.0
     } else {
@@ -49,7 +202,10 @@ pub fn add_velocity_to_pointer(velocity: Velocity, mut pointer: Pointer) -> (usi
 
 #[cfg(test)]
 mod test {
-    use super::add_velocity_to_pointer;
+    use super::{
+        add_velocity_to_pointer, velocity_name, velocity_to_arrow, Direction, InvalidVelocity,
+        Velocity,
+    };
 
     macro_rules! add_velocity_to_pointer_tests {
         ( $name:ident, $pointer:expr, $(( $test:literal, $expected:expr )),* , ) => {
@@ -95,4 +251,77 @@ mod test {
         (0b10, (usize::MAX, 0)),              // Down
         (0b11, (usize::MAX, usize::MAX - 1)), // Up
     );
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn source_position_display() {
+        let position = super::SourcePosition { line: 6, column: 0 };
+
+        assert_eq!(position.to_string(), "line 7, column 1");
+    }
+
+    #[test]
+    fn source_position_byte_offset() {
+        let position = |column| super::SourcePosition { line: 0, column };
+
+        assert_eq!(position(2).byte_offset("abc"), Some(2));
+        assert_eq!(position(2).byte_offset("\u{e9}\u{1F600}c"), Some(6));
+        assert_eq!(position(3).byte_offset("\u{e9}\u{1F600}c"), Some(7));
+        assert_eq!(position(4).byte_offset("\u{e9}\u{1F600}c"), None);
+        assert_eq!(position(0).byte_offset(""), Some(0));
+    }
+
+    #[test]
+    fn velocity_helpers() {
+        let tests = [
+            (0b00, '>', "Right"),
+            (0b01, '<', "Left"),
+            (0b10, 'v', "Down"),
+            (0b11, '^', "Up"),
+        ];
+
+        for (velocity, arrow, name) in tests {
+            // The high bits should be ignored
+            for high_bits in [0b0000_0000, 0b0000_0100, 0b1111_1100] {
+                assert_eq!(velocity_to_arrow(velocity | high_bits), arrow);
+                assert_eq!(velocity_name(velocity | high_bits), name);
+            }
+        }
+    }
+
+    #[test]
+    fn direction_round_trip() {
+        let tests = [
+            (Direction::Right, 0b00),
+            (Direction::Left, 0b01),
+            (Direction::Down, 0b10),
+            (Direction::Up, 0b11),
+        ];
+        assert_eq!(tests.map(|(direction, _)| direction), Direction::ALL);
+
+        for (direction, velocity) in tests {
+            assert_eq!(Velocity::from(direction), velocity);
+            assert_eq!(Direction::try_from(velocity), Ok(direction));
+            assert_eq!(
+                Direction::try_from(Velocity::from(direction)),
+                Ok(direction)
+            );
+            assert_eq!(
+                Direction::from_velocity_bits(velocity | 0b1111_1100),
+                direction
+            );
+            assert_eq!(direction.arrow(), velocity_to_arrow(velocity));
+            assert_eq!(direction.name(), velocity_name(velocity));
+        }
+    }
+
+    #[test]
+    fn direction_invalid_velocity() {
+        for velocity in [0b100, 0b111, Velocity::MAX] {
+            assert_eq!(
+                Direction::try_from(velocity),
+                Err(InvalidVelocity(velocity))
+            );
+        }
+    }
 }
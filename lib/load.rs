@@ -4,80 +4,146 @@
 use std::{
     error,
     fmt::Display,
-    io::{BufRead, Stdin},
-    iter::once,
+    fs,
+    io::{self, BufRead, Stdin},
+    mem,
     num::ParseIntError,
+    path::{Path, PathBuf},
 };
 
 use crate::{
+    build,
     instruction::{Instruction, IntoInstructionError},
-    machine,
+    is_shebang, machine,
     plane::{Plane, VecPlane},
+    program,
     stack::VecStack,
-    Number, Pointer,
+    Number, Pointer, SourcePosition,
 };
 
-/// `MSCode` load errors
+/// `MSCode` load errors. Number parse errors only have to implement
+/// [`Display`], but [`error::Error`] is only implemented if they do too
 #[derive(Debug)]
-pub enum Error<ParseNError: error::Error> {
+pub enum Error<ParseNError: Display> {
     /// Invalid instruction character
     InvalidInstruction(IntoInstructionError),
     /// Invalid number
     InvalidNumber(ParseNError),
     /// Invalid coordinate number
     InvalidCoordinate(ParseIntError),
-    /// Stack coordinate greater than or equal to 1/4 of the width / height
-    StackPointerOutOfRange(Pointer),
+    /// Stack coordinate greater than or equal to 1/4 of the width / height,
+    /// with the width and height of the stack plane
+    StackPointerOutOfRange(Pointer, (usize, usize)),
     /// Missing at least one coordinate in a stack line
     MissingStackPointer(String),
+    /// Missing the coordinates or capacity in a capacity line
+    MissingCapacity(String),
+    /// An extra token at the end of a capacity line
+    StrayToken(String),
+    /// An error at a position in the source, when loading in strict mode
+    At(SourcePosition, Box<Self>),
+    /// Failed to read the source
+    Io(io::Error),
+    /// A file includes itself, directly or through other files
+    IncludeCycle(PathBuf),
+    /// Files are included inside each other more than
+    /// [`MAX_INCLUDE_DEPTH`] times
+    IncludeTooDeep(PathBuf),
 }
 
 impl<E: error::Error + 'static> error::Error for Error<E> {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        use Error::{InvalidCoordinate, InvalidInstruction, InvalidNumber};
+        use Error::{At, InvalidCoordinate, InvalidInstruction, InvalidNumber, Io};
         match self {
             InvalidInstruction(err) => Some(err),
             InvalidNumber(err) => Some(err),
             InvalidCoordinate(err) => Some(err),
+            Io(err) => Some(err),
+            At(_, err) => Some(err),
             _ => None,
         }
     }
 }
 
-impl<E: error::Error> Display for Error<E> {
+impl<E: Display> Display for Error<E> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Error::{
-            InvalidCoordinate, InvalidInstruction, InvalidNumber, MissingStackPointer,
-            StackPointerOutOfRange,
+            At, IncludeCycle, IncludeTooDeep, InvalidCoordinate, InvalidInstruction, InvalidNumber,
+            Io, MissingCapacity, MissingStackPointer, StackPointerOutOfRange, StrayToken,
         };
         match self {
             InvalidInstruction(err) => err.fmt(f),
             InvalidNumber(err) => Display::fmt(&err, f),
             InvalidCoordinate(err) => err.fmt(f),
-            StackPointerOutOfRange(pointer) => {
-                write!(f, "stack pointer out of range: {pointer:?}")
-            }
+            StackPointerOutOfRange(pointer, (width, height)) => write!(
+                f,
+                "stack pointer out of range: {pointer:?}; valid range is 0..{width} × 0..{height}"
+            ),
             MissingStackPointer(line) => write!(f, "stack line missing pointer: \"{line:?}\""),
+            MissingCapacity(line) => write!(f, "capacity line missing capacity: \"{line:?}\""),
+            StrayToken(token) => write!(f, "unexpected token: {token:?}"),
+            At(position, err) => write!(f, "{err} at {position}"),
+            Io(err) => write!(f, "failed to read source: {err}"),
+            IncludeCycle(path) => write!(f, "\"{}\" is included inside itself", path.display()),
+            IncludeTooDeep(path) => write!(
+                f,
+                "\"{}\" is included more than {MAX_INCLUDE_DEPTH} files deep",
+                path.display()
+            ),
         }
     }
 }
 
-impl<E: error::Error> From<IntoInstructionError> for Error<E> {
+impl<E: Display> From<IntoInstructionError> for Error<E> {
     fn from(value: IntoInstructionError) -> Self {
         Self::InvalidInstruction(value)
     }
 }
 
-impl<E: error::Error> From<ParseIntError> for Error<E> {
+impl<E: Display> From<ParseIntError> for Error<E> {
     fn from(value: ParseIntError) -> Self {
         Self::InvalidCoordinate(value)
     }
 }
 
+/// Convert to a [`build::Error`], so that errors from both loaders can
+/// be handled together. The line of a
+/// [`MissingStackPointer`](Error::MissingStackPointer) error, the stack
+/// plane dimensions of a
+/// [`StackPointerOutOfRange`](Error::StackPointerOutOfRange) error and the
+/// position of an [`At`](Error::At) error are dropped. Include errors
+/// become [`Io`](build::Error::Io) errors with the
+/// [`InvalidData`](io::ErrorKind::InvalidData) kind
+impl<E: Display> From<Error<E>> for build::Error<E, ParseIntError> {
+    fn from(value: Error<E>) -> Self {
+        use Error::{
+            At, IncludeCycle, IncludeTooDeep, InvalidCoordinate, InvalidInstruction, InvalidNumber,
+            Io, MissingCapacity, MissingStackPointer, StackPointerOutOfRange, StrayToken,
+        };
+        match value {
+            InvalidInstruction(err) => Self::InvalidInstruction(err),
+            InvalidNumber(err) => Self::InvalidNumber(err),
+            InvalidCoordinate(err) => Self::InvalidCoordinate(err),
+            StackPointerOutOfRange(pointer, _) => Self::StackPointerOutOfRange(pointer),
+            MissingStackPointer(_) => Self::MissingStackPointer,
+            MissingCapacity(_) => Self::MissingCapacity,
+            StrayToken(_) => Self::StrayToken,
+            At(_, err) => Self::from(*err),
+            Io(err) => Self::Io(err),
+            err @ (IncludeCycle(_) | IncludeTooDeep(_)) => {
+                Self::Io(io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+            }
+        }
+    }
+}
+
 /// The returned machine type when loaded
 pub type Machine<N> =
     machine::Machine<N, VecPlane<Instruction>, VecStack<N>, VecPlane<VecStack<N>>>;
 
+/// A [`Program`](program::Program) using Vec based structures
+pub type Program<N> = program::Program<N, VecPlane<Instruction>, VecPlane<VecStack<N>>>;
+
 /// Load `MSCode` from a str
 ///
 /// # Errors
@@ -86,26 +152,57 @@ pub type Machine<N> =
 /// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
 /// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
-pub fn from_str<N: Number, ParseNError: error::Error>(
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+pub fn from_str<N: Number, ParseNError: Display>(
     source: &str,
     try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
 ) -> Result<Machine<N>, Error<ParseNError>> {
-    let mut instructions = Vec::new();
-    let mut stack_instructions = Vec::new();
-
+    let mut parser = Parser::new(try_parse_n);
     for line in source.lines() {
-        parse_line(
-            line,
-            &mut instructions,
-            &mut stack_instructions,
-            try_parse_n,
-        )?;
+        parser.feed_line(line)?;
     }
+    parser.finish()
+}
 
-    let instructions: VecPlane<Instruction> = instructions.into();
-    let stacks = create_stacks(stack_instructions, &instructions)?;
+/// Options for loading `MSCode`
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct LoadOptions {
+    /// How anything that cannot be parsed is handled
+    pub mode: Mode,
+}
+
+/// How the loader handles anything in the source that cannot be parsed
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Mode {
+    /// Reject it, like [`from_str`]
+    #[default]
+    Default,
+    /// Reject it, with an [`Error::At`] giving its position
+    Strict,
+    /// Skip it: unknown instruction characters are loaded as spaces,
+    /// malformed stack numbers are skipped and header lines with
+    /// malformed coordinates are ignored
+    Lenient,
+}
 
-    Ok(Machine::new(instructions, stacks))
+/// Load `MSCode` from a str with [`LoadOptions`]
+///
+/// # Errors
+/// See [`from_str`], which this matches with the default options. In
+/// strict mode, parse errors are wrapped in an
+/// [`Error::At`], and an extra token at the end of a capacity line is an
+/// [`Error::StrayToken`]. In lenient mode, only
+/// [`Error::StackPointerOutOfRange`] is returned
+pub fn from_str_with_options<N: Number, ParseNError: Display>(
+    source: &str,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+    options: LoadOptions,
+) -> Result<Machine<N>, Error<ParseNError>> {
+    let mut parser = Parser::with_options(try_parse_n, options);
+    for line in source.lines() {
+        parser.feed_line(line)?;
+    }
+    parser.finish()
 }
 
 /// Load `MSCode` from stdin
@@ -116,124 +213,1317 @@ pub fn from_str<N: Number, ParseNError: error::Error>(
 /// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
 /// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
-pub fn from_stdin<N: Number, ParseNError: error::Error>(
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+/// - [`Error::Io`] - failed to read a line
+pub fn from_stdin<N: Number, ParseNError: Display>(
     source: &Stdin,
     try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
 ) -> Result<Machine<N>, Error<ParseNError>> {
-    let mut instructions = Vec::new();
-    let mut stack_instructions = Vec::new();
+    from_reader(source.lock(), try_parse_n)
+}
 
-    let mut lines = source.lock().lines();
-    while let Some(Ok(line)) = lines.next() {
-        parse_line(
-            &line,
-            &mut instructions,
-            &mut stack_instructions,
+/// Load `MSCode` from a reader
+///
+/// # Errors
+/// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
+/// - [`Error::InvalidNumber`] - failed to parse a number
+/// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
+/// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+/// - [`Error::Io`] - failed to read a line
+pub fn from_reader<N: Number, ParseNError: Display>(
+    source: impl BufRead,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+) -> Result<Machine<N>, Error<ParseNError>> {
+    let mut parser = Parser::new(try_parse_n);
+    for line in source.lines() {
+        parser.feed_line(&line.map_err(Error::Io)?)?;
+    }
+    parser.finish()
+}
+
+/// The most files that can be included inside each other, not counting
+/// the file or source they are included from, see
+/// [`from_str_with_includes`]
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Load `MSCode` from a str, splicing in the lines of included files.
+///
+/// A line of the form `include "path"` is replaced by the lines of the
+/// file at `path`, relative to `base` (or to the including file, for
+/// nested includes). The body lines of an included file become rows at
+/// that point, so the rows after the include line are moved down by
+/// them, and its header lines apply as if they were in the including
+/// source. The include line itself is not a row. The source positions of
+/// included rows are the position of the include line in `source`
+///
+/// # Errors
+/// See [`from_str`], and:
+/// - [`Error::Io`] - failed to read an included file
+/// - [`Error::IncludeCycle`] - a file includes itself
+/// - [`Error::IncludeTooDeep`] - files are included more than [`MAX_INCLUDE_DEPTH`] deep
+pub fn from_str_with_includes<N: Number, ParseNError: Display>(
+    source: &str,
+    base: impl AsRef<Path>,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+) -> Result<Machine<N>, Error<ParseNError>> {
+    let mut parser = Parser::new(try_parse_n);
+    let mut including = Vec::new();
+    for line in source.lines() {
+        parser.feed_line_with_includes(line, base.as_ref(), &mut including)?;
+    }
+    parser.finish()
+}
+
+/// Load `MSCode` from a file, with includes relative to the file,
+/// see [`from_str_with_includes`]
+///
+/// # Errors
+/// See [`from_str_with_includes`]. [`Error::Io`] is also returned if the
+/// file cannot be read
+pub fn from_file<N: Number, ParseNError: Display>(
+    path: impl AsRef<Path>,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+) -> Result<Machine<N>, Error<ParseNError>> {
+    let path = path.as_ref().canonicalize().map_err(Error::Io)?;
+    let source = fs::read_to_string(&path).map_err(Error::Io)?;
+    let base = path.parent().unwrap_or(&path).to_owned();
+
+    let mut parser = Parser::new(try_parse_n);
+    // The file cannot include itself
+    let mut including = vec![path];
+    for line in source.lines() {
+        parser.feed_line_with_includes(line, &base, &mut including)?;
+    }
+    parser.finish()
+}
+
+/// Get the path of an `include "path"` line
+fn include_path(line: &str) -> Option<&str> {
+    line.strip_prefix("include ")?
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+/// The path of a file loaded by [`from_dir`] and the result of loading it
+pub type LoadedFile<N, ParseNError> = (PathBuf, Result<Machine<N>, Error<ParseNError>>);
+
+/// Load every `.msc` file in a directory, sorted by path.
+/// Subdirectories are not searched
+///
+/// # Errors
+/// Returns an error if the directory cannot be read. Errors from
+/// reading or loading each file are returned with its path, see
+/// [`from_str`], and [`Error::Io`] if the file cannot be read
+pub fn from_dir<N: Number, ParseNError: Display>(
+    path: impl AsRef<Path>,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+) -> io::Result<Vec<LoadedFile<N, ParseNError>>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "msc") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let machine = fs::read_to_string(&path)
+                .map_err(Error::Io)
+                .and_then(|source| from_str(&source, try_parse_n));
+            (path, machine)
+        })
+        .collect())
+}
+
+/// Shrink `source` while `predicate` holds for the machine it loads to
+///
+/// Header lines and comments are removed and instructions are replaced
+/// with spaces greedily. Each machine is given a step limit of
+/// `step_limit`, so candidates that never stop still halt.
+/// Returns `source` unchanged if the predicate does not hold for it
+pub fn minimize<N: Number, ParseNError: Display>(
+    source: &str,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+    step_limit: usize,
+    mut predicate: impl FnMut(Machine<N>) -> bool,
+) -> String {
+    let mut holds = |lines: &[String]| {
+        from_str(&lines.join("\n"), try_parse_n).is_ok_and(|mut machine| {
+            machine.set_step_limit(Some(step_limit));
+            predicate(machine)
+        })
+    };
+
+    let mut lines: Vec<String> = source.lines().map(str::to_owned).collect();
+    if !holds(&lines) {
+        return source.to_owned();
+    }
+
+    // Removing one part can make another removable, so repeat until
+    // nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut index = 0;
+        while index < lines.len() {
+            if matches!(lines[index].chars().next(), Some('#' | 'C' | 's')) {
+                let removed = lines.remove(index);
+                if holds(&lines) {
+                    changed = true;
+                    continue;
+                }
+                lines.insert(index, removed);
+            } else {
+                changed |= minimize_code_line(&mut lines, index, &mut holds);
+            }
+            index += 1;
+        }
+    }
+
+    // Trailing spaces and empty lines may still change the dimensions
+    let mut trimmed: Vec<String> = lines
+        .iter()
+        .map(|line| line.trim_end().to_owned())
+        .collect();
+    while trimmed.last().is_some_and(String::is_empty) {
+        trimmed.pop();
+    }
+    if holds(&trimmed) {
+        lines = trimmed;
+    }
+    lines.join("\n")
+}
+
+/// Remove the comment and replace the instructions of the code line at
+/// `index` with spaces while `holds`, returning whether anything changed
+fn minimize_code_line(
+    lines: &mut [String],
+    index: usize,
+    holds: &mut impl FnMut(&[String]) -> bool,
+) -> bool {
+    let mut changed = false;
+    let mut chars: Vec<char> = lines[index].chars().collect();
+    let comment = chars.iter().position(|&char| char == '#');
+
+    if let Some(comment) = comment {
+        let original = mem::replace(&mut lines[index], chars[..comment].iter().collect());
+        if holds(lines) {
+            changed = true;
+            chars.truncate(comment);
+        } else {
+            lines[index] = original;
+        }
+    }
+    let end = comment.unwrap_or(chars.len());
+
+    for column in 0..end {
+        if chars[column] == ' ' {
+            continue;
+        }
+        let original = mem::replace(&mut chars[column], ' ');
+        lines[index] = chars.iter().collect();
+        if holds(lines) {
+            changed = true;
+        } else {
+            chars[column] = original;
+        }
+    }
+    lines[index] = chars.iter().collect();
+    changed
+}
+
+/// A number that can be parsed in a base, for [`parse_n_extended`]
+pub trait FromStrRadix: Sized {
+    /// Parse a number in base `radix`, with an optional sign
+    ///
+    /// # Errors
+    /// Returns an error if `src` is not a valid number in base `radix`
+    fn from_str_radix(src: &str, radix: u32) -> Result<Self, ParseIntError>;
+}
+
+macro_rules! from_str_radix_impl {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl FromStrRadix for $t {
+                fn from_str_radix(src: &str, radix: u32) -> Result<Self, ParseIntError> {
+                    <$t>::from_str_radix(src, radix)
+                }
+            }
+
+            impl FromStrRadix for core::num::Wrapping<$t> {
+                fn from_str_radix(src: &str, radix: u32) -> Result<Self, ParseIntError> {
+                    <$t>::from_str_radix(src, radix).map(Self)
+                }
+            }
+        )*
+    };
+}
+
+from_str_radix_impl!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// [`parse_n_extended`] errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseExtendedError {
+    /// A `_` that is not between two digits, or a sign after the prefix
+    Malformed,
+    /// The digits are not a valid number
+    Invalid(ParseIntError),
+}
+
+impl error::Error for ParseExtendedError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Malformed => None,
+            Self::Invalid(err) => Some(err),
+        }
+    }
+}
+
+impl Display for ParseExtendedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "misplaced separator or sign"),
+            Self::Invalid(err) => err.fmt(f),
+        }
+    }
+}
+
+/// Parse a number that may have a `0x` (hexadecimal) or `0b` (binary)
+/// prefix after its sign, and `_` separators between digits.
+/// This can be used as the `try_parse_n` argument of the loaders
+///
+/// # Errors
+/// - [`ParseExtendedError::Malformed`] - a `_` is not between two digits,
+///   or there is a sign after the prefix
+/// - [`ParseExtendedError::Invalid`] - the digits are not a valid number
+pub fn parse_n_extended<N: FromStrRadix>(value: &str) -> Result<N, ParseExtendedError> {
+    let (sign, unsigned) = value.strip_prefix('-').map_or_else(
+        || ("", value.strip_prefix('+').unwrap_or(value)),
+        |unsigned| ("-", unsigned),
+    );
+    let (radix, digits) = [(16, "0x"), (2, "0b")]
+        .into_iter()
+        .find_map(|(radix, prefix)| Some((radix, unsigned.strip_prefix(prefix)?)))
+        .unwrap_or((10, unsigned));
+
+    let malformed =
+        digits.starts_with(['_', '+', '-']) || digits.ends_with('_') || digits.contains("__");
+    if malformed {
+        return Err(ParseExtendedError::Malformed);
+    }
+    N::from_str_radix(&format!("{sign}{}", digits.replace('_', "")), radix)
+        .map_err(ParseExtendedError::Invalid)
+}
+
+/// An incremental `MSCode` loader that is fed one line at a time
+pub struct Parser<'a, N, ParseNError> {
+    try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>,
+    instructions: Vec<Vec<Instruction>>,
+    stack_instructions: Vec<(usize, usize, Vec<N>)>,
+    capacities: Vec<(usize, usize, usize)>,
+    /// The source line of each instruction row
+    instruction_lines: Vec<usize>,
+    /// The index of the next line
+    line: usize,
+    mode: Mode,
+}
+
+impl<'a, N: Number, ParseNError: Display> Parser<'a, N, ParseNError> {
+    /// Create a parser that reports errors without positions
+    #[must_use]
+    pub fn new(try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>) -> Self {
+        Self {
             try_parse_n,
+            instructions: Vec::new(),
+            stack_instructions: Vec::new(),
+            capacities: Vec::new(),
+            instruction_lines: Vec::new(),
+            line: 0,
+            mode: Mode::Default,
+        }
+    }
+
+    /// Create a parser that handles errors as set in `options`
+    #[must_use]
+    pub fn with_options(
+        try_parse_n: &'a dyn Fn(&str) -> Result<N, ParseNError>,
+        options: LoadOptions,
+    ) -> Self {
+        Self {
+            mode: options.mode,
+            ..Self::new(try_parse_n)
+        }
+    }
+
+    /// Parse the next line of `MSCode`
+    ///
+    /// # Errors
+    /// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
+    /// - [`Error::InvalidNumber`] - failed to parse a number
+    /// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
+    /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+    /// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+    /// - [`Error::StrayToken`] - an extra token at the end of a capacity line (strict only)
+    /// - [`Error::At`] - any of the above, with its position (strict only)
+    pub fn feed_line(&mut self, line: &str) -> Result<(), Error<ParseNError>> {
+        let index = self.line;
+        self.line += 1;
+
+        // Skip the shebang, if there is one
+        if index == 0 && is_shebang(line) {
+            return Ok(());
+        }
+
+        self.parse_at(line, index)
+    }
+
+    /// Parse the next line like [`Parser::feed_line`], but replace an
+    /// include line with the lines of the file, which is relative to
+    /// `base`. `including` holds the canonical paths of the files being
+    /// included, and of the root file if there is one, to find cycles
+    fn feed_line_with_includes(
+        &mut self,
+        line: &str,
+        base: &Path,
+        including: &mut Vec<PathBuf>,
+    ) -> Result<(), Error<ParseNError>> {
+        let Some(path) = include_path(line) else {
+            return self.feed_line(line);
+        };
+
+        let index = self.line;
+        self.line += 1;
+        self.include(&base.join(path), index, including, 0)
+    }
+
+    /// Parse the lines of an included file, as if they were at the
+    /// source line `index`, following nested includes. `depth` is the
+    /// number of included files this is inside of
+    fn include(
+        &mut self,
+        path: &Path,
+        index: usize,
+        including: &mut Vec<PathBuf>,
+        depth: usize,
+    ) -> Result<(), Error<ParseNError>> {
+        let canonical = path.canonicalize().map_err(Error::Io)?;
+        if including.contains(&canonical) {
+            return Err(Error::IncludeCycle(path.to_owned()));
+        }
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(Error::IncludeTooDeep(path.to_owned()));
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(Error::Io)?;
+        let base = canonical.parent().unwrap_or(&canonical).to_owned();
+        including.push(canonical);
+
+        for (number, line) in source.lines().enumerate() {
+            // Included files may also have shebangs
+            if number == 0 && is_shebang(line) {
+                continue;
+            }
+
+            match include_path(line) {
+                Some(nested) => {
+                    self.include(&base.join(nested), index, including, depth + 1)?;
+                }
+                None => self.parse_at(line, index)?,
+            }
+        }
+
+        including.pop();
+        Ok(())
+    }
+
+    /// Parse a line, where `index` is the line in the source
+    fn parse_at(&mut self, line: &str, index: usize) -> Result<(), Error<ParseNError>> {
+        let rows = self.instructions.len();
+        parse_line_with_mode(
+            line,
+            index,
+            &mut self.instructions,
+            &mut self.stack_instructions,
+            &mut self.capacities,
+            self.try_parse_n,
+            self.mode,
         )?;
+        // Record the source line of each instruction row
+        if self.instructions.len() > rows {
+            self.instruction_lines.push(index);
+        }
+
+        Ok(())
     }
 
-    let instructions: VecPlane<Instruction> = instructions.into();
-    let stacks = create_stacks(stack_instructions, &instructions)?;
+    /// The stack capacities requested by capacity lines fed so far,
+    /// as `(x, y, capacity)`
+    #[must_use]
+    pub fn capacities(&self) -> &[(usize, usize, usize)] {
+        &self.capacities
+    }
 
-    Ok(Machine::new(instructions, stacks))
+    /// Create the machine from the lines fed so far
+    ///
+    /// # Errors
+    /// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
+    pub fn finish(self) -> Result<Machine<N>, Error<ParseNError>> {
+        let instructions: VecPlane<Instruction> = self.instructions.into();
+        let stacks = create_stacks(self.stack_instructions, &self.capacities, &instructions)?;
+        let positions = create_positions(&self.instruction_lines, instructions.width());
+
+        Ok(Machine::new(instructions, stacks).with_source_positions(positions))
+    }
 }
 
-/// Load one line of `MSCode` from a str
+/// Load one line of `MSCode` from a str.
+/// Capacity lines are checked, but their capacities are discarded; see
+/// [`parse_line_with_capacities`] to keep them
 ///
 /// # Errors
 /// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
 /// - [`Error::InvalidNumber`] - failed to parse a number
 /// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
 /// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
-pub fn parse_line<N: Number, ParseNError: error::Error>(
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+pub fn parse_line<N: Number, ParseNError: Display>(
     line: &str,
     instructions: &mut Vec<Vec<Instruction>>,
     stack_instructions: &mut Vec<(usize, usize, Vec<N>)>,
     try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
 ) -> Result<(), Error<ParseNError>> {
-    let mut chars = line.chars();
-    match chars.next() {
-        Some('#') => {}
-        Some('s') => {
-            // Remove comments
-            let line_string =
-                chars
-                    .take_while(|&char| char != '#')
-                    .fold(String::new(), |mut code_line, char| {
-                        code_line.push(char);
-                        code_line
-                    });
-            let mut numbers_string = line_string.split_whitespace();
+    parse_line_with_capacities(
+        line,
+        instructions,
+        stack_instructions,
+        &mut Vec::new(),
+        try_parse_n,
+    )
+}
+
+/// Load one line of `MSCode` from a str like [`parse_line`], adding the
+/// capacities of capacity lines to `capacities` as `(x, y, capacity)`
+///
+/// # Errors
+/// - [`Error::InvalidInstruction`] - failed to parse a character as an instruction
+/// - [`Error::InvalidNumber`] - failed to parse a number
+/// - [`Error::InvalidCoordinate`] - failed to parse a coordinate number
+/// - [`Error::MissingStackPointer`] - missing at least one coordinate in a stack line
+/// - [`Error::MissingCapacity`] - missing the coordinates or capacity in a capacity line
+pub fn parse_line_with_capacities<N: Number, ParseNError: Display>(
+    line: &str,
+    instructions: &mut Vec<Vec<Instruction>>,
+    stack_instructions: &mut Vec<(usize, usize, Vec<N>)>,
+    capacities: &mut Vec<(usize, usize, usize)>,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+) -> Result<(), Error<ParseNError>> {
+    parse_line_with_mode(
+        line,
+        0,
+        instructions,
+        stack_instructions,
+        capacities,
+        try_parse_n,
+        Mode::Default,
+    )
+}
+
+/// Split a header line into whitespace separated tokens with their
+/// columns, stopping at a comment
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    // Skip the header character
+    let code = line.get(1..).unwrap_or_default();
+    let code = code.split('#').next().unwrap_or_default();
+    code.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - code.as_ptr() as usize;
+        (code[..offset].chars().count() + 1, token)
+    })
+}
+
+/// Load one line of `MSCode`, where `index` is the line in the source
+fn parse_line_with_mode<N: Number, ParseNError: Display>(
+    line: &str,
+    index: usize,
+    instructions: &mut Vec<Vec<Instruction>>,
+    stack_instructions: &mut Vec<(usize, usize, Vec<N>)>,
+    capacities: &mut Vec<(usize, usize, usize)>,
+    try_parse_n: &dyn Fn(&str) -> Result<N, ParseNError>,
+    mode: Mode,
+) -> Result<(), Error<ParseNError>> {
+    let lenient = matches!(mode, Mode::Lenient);
+    // Add the position to an error in strict mode
+    let at = |column, err| match mode {
+        Mode::Strict => Error::At(
+            SourcePosition {
+                line: index,
+                column,
+            },
+            Box::new(err),
+        ),
+        Mode::Default | Mode::Lenient => err,
+    };
+    let coordinate = |(column, token): (usize, &str)| -> Result<usize, Error<ParseNError>> {
+        token
+            .parse()
+            .map_err(|err: ParseIntError| at(column, err.into()))
+    };
+
+    let result = match line.chars().next() {
+        Some('#') => Ok(()),
+        Some('C') => (|| {
+            let mut tokens = tokens(line);
+
+            let (Some(x), Some(y), Some(capacity)) = (tokens.next(), tokens.next(), tokens.next())
+            else {
+                return Err(at(0, Error::MissingCapacity(line.to_owned())));
+            };
+
+            let capacity = (coordinate(x)?, coordinate(y)?, coordinate(capacity)?);
+            if let (Mode::Strict, Some((column, token))) = (mode, tokens.next()) {
+                return Err(at(column, Error::StrayToken(token.to_owned())));
+            }
+
+            capacities.push(capacity);
+            Ok(())
+        })(),
+        Some('s') => (|| {
+            let mut tokens = tokens(line);
 
             // Pop x and y off from the numbers
-            let (Some(x), Some(y)) = (numbers_string.next(), numbers_string.next()) else {
-                    return Err(Error::MissingStackPointer(line.to_owned()));
-                };
+            let (Some(x), Some(y)) = (tokens.next(), tokens.next()) else {
+                return Err(at(0, Error::MissingStackPointer(line.to_owned())));
+            };
 
-            let (x, y) = (x.parse()?, y.parse()?);
+            let (x, y) = (coordinate(x)?, coordinate(y)?);
 
             // Collect the rest of the numbers into a stack
-            let stack: Result<Vec<N>, ParseNError> = numbers_string
-                .map(|number_str| try_parse_n(number_str))
-                .collect();
-            stack_instructions.push((
-                x,
-                y,
-                match stack {
-                    Ok(value) => value,
-                    Err(err) => return Err(Error::InvalidNumber(err)),
-                },
-            ));
-        }
-        Some(char) => {
-            let code_line: Result<Vec<Instruction>, IntoInstructionError> = once(char)
-                .chain(chars)
-                // Remove comments
-                .map_while(|char| {
-                    if char == '#' {
-                        None
-                    } else {
-                        Some(Instruction::try_from(char))
-                    }
-                })
-                .collect();
+            let mut stack = Vec::new();
+            for (column, token) in tokens {
+                match try_parse_n(token) {
+                    Ok(value) => stack.push(value),
+                    Err(_) if lenient => {}
+                    Err(err) => return Err(at(column, Error::InvalidNumber(err))),
+                }
+            }
+            stack_instructions.push((x, y, stack));
+            Ok(())
+        })(),
+        Some(_) => {
+            let mut code_line = Vec::new();
+            // Remove comments
+            for (column, char) in line
+                .chars()
+                .enumerate()
+                .take_while(|&(_, char)| char != '#')
+            {
+                match Instruction::try_from(char) {
+                    Ok(instruction) => code_line.push(instruction),
+                    // Keep the columns of the following instructions
+                    Err(_) if lenient => code_line.push(Instruction::Space),
+                    Err(err) => return Err(at(column, err.into())),
+                }
+            }
 
-            instructions.push(code_line?);
+            instructions.push(code_line);
+            Ok(())
         }
         None => {
             instructions.push(Vec::new());
+            Ok(())
         }
     };
-    Ok(())
+
+    // Ignore header lines that cannot be parsed in lenient mode
+    match result {
+        Err(_) if lenient => Ok(()),
+        result => result,
+    }
 }
 
-/// Create stacks from `stack_instructions`
+/// The most stacks in a stack plane fitted to the referenced stacks, see
+/// [`create_stacks`]
+pub const MAX_FITTED_STACKS: usize = 1 << 16;
+
+/// The most space allocated up front for a stack by a capacity line, as
+/// the capacity is only a hint
+pub const MAX_CAPACITY_HINT: usize = 1 << 16;
+
+/// Create stacks from `stack_instructions`, allocating at least the
+/// capacity in `capacities` for each stack, up to [`MAX_CAPACITY_HINT`]
+///
+/// The stack plane is 1/4 of the width and height of the instructions,
+/// rounded up without overflowing, but is made large enough for the
+/// referenced stacks if there are no instructions, up to
+/// [`MAX_FITTED_STACKS`] stacks
 ///
 /// # Errors
-/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height
-pub fn create_stacks<N: Number, ParseNError: error::Error>(
+/// - [`Error::StackPointerOutOfRange`] - a stack coordinate is greater than or equal to 1/4 of the width / height,
+///   or would make the fitted stack plane larger than [`MAX_FITTED_STACKS`]
+pub fn create_stacks<N: Number, ParseNError: Display>(
     stack_instructions: Vec<(usize, usize, Vec<N>)>,
-    instructions: &VecPlane<Instruction>,
+    capacities: &[(usize, usize, usize)],
+    instructions: &impl Plane<Item = Instruction>,
 ) -> Result<VecPlane<VecStack<N>>, Error<ParseNError>> {
-    // Create blank stacks
-    let mut stacks: VecPlane<VecStack<N>> =
-        vec![
-            vec![VecStack::new(); (instructions.width() + 3) / 4];
-            (instructions.height() + 3) / 4
-        ]
+    let (width, height) = if instructions.width() == 0 || instructions.height() == 0 {
+        // Without instructions to size the stack plane by, fit the stacks
+        // that are referenced. Stacks that would make it too large are
+        // left out of range
+        stack_instructions
+            .iter()
+            .map(|&(x, y, _)| (x, y))
+            .chain(capacities.iter().map(|&(x, y, _)| (x, y)))
+            .fold(
+                (
+                    instructions.width().div_ceil(4),
+                    instructions.height().div_ceil(4),
+                ),
+                |(width, height), (x, y)| {
+                    let fitted = (
+                        width.max(x.saturating_add(1)),
+                        height.max(y.saturating_add(1)),
+                    );
+                    match fitted.0.checked_mul(fitted.1) {
+                        Some(area) if area <= MAX_FITTED_STACKS => fitted,
+                        _ => (width, height),
+                    }
+                },
+            )
+    } else {
+        (
+            instructions.width().div_ceil(4),
+            instructions.height().div_ceil(4),
+        )
+    };
+
+    // Check the coordinates before allocating anything
+    let out_of_range = stack_instructions
+        .iter()
+        .map(|&(x, y, _)| (x, y))
+        .chain(capacities.iter().map(|&(x, y, _)| (x, y)))
+        .find(|&(x, y)| x >= width || y >= height);
+    if let Some(pointer) = out_of_range {
+        return Err(Error::StackPointerOutOfRange(pointer, (width, height)));
+    }
+
+    // Count the items for each stack, so that each is only allocated once
+    // Only allocate rows that exist, so that a wide plane with no rows
+    // does not allocate
+    let mut sizes: Vec<Vec<usize>> = (0..height).map(|_| vec![0; width]).collect();
+    // The coordinates have already been checked
+    for &(x, y, ref new_stack) in &stack_instructions {
+        if let Some(size) = sizes.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *size += new_stack.len();
+        }
+    }
+    for &(x, y, capacity) in capacities {
+        if let Some(size) = sizes.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *size = (*size).max(capacity.min(MAX_CAPACITY_HINT));
+        }
+    }
+
+    // Create empty stacks
+    let mut stacks: VecPlane<VecStack<N>> = sizes
+        .into_iter()
+        .map(|row| row.into_iter().map(VecStack::with_capacity).collect())
+        .collect::<Vec<Vec<_>>>()
         .into();
 
     for (x, y, new_stack) in stack_instructions {
-        // Attempt to get a reference to the stack
-        let stack = match stacks.get_mut((x, y)) {
-            Some(stack) => stack,
-            None => return Err(Error::StackPointerOutOfRange((x, y))),
+        // The coordinates have already been checked
+        if let Some(stack) = stacks.get_mut((x, y)) {
+            // Concatenate the stacks
+            stack.extend(new_stack);
+        }
+    }
+
+    Ok(stacks)
+}
+
+/// Create the source positions of instructions from the source line
+/// of each instruction row
+#[must_use]
+pub fn create_positions(instruction_lines: &[usize], width: usize) -> VecPlane<SourcePosition> {
+    instruction_lines
+        .iter()
+        .map(|&line| {
+            (0..width)
+                .map(|column| SourcePosition { line, column })
+                .collect()
+        })
+        .collect::<Vec<Vec<SourcePosition>>>()
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fmt::Display, num::ParseIntError};
+
+    use crate::{
+        build, instruction::Instruction, machine::State, plane::Plane, stack::Stack, Pointer,
+        SourcePosition,
+    };
+
+    use std::{
+        env, fs,
+        io::{self, BufReader, Read},
+    };
+
+    use super::{
+        create_stacks, from_dir, from_file, from_reader, from_str, from_str_with_includes,
+        from_str_with_options, minimize, parse_line, parse_line_with_capacities, parse_n_extended,
+        Error, LoadOptions, Machine, Mode, ParseExtendedError, Parser, MAX_INCLUDE_DEPTH,
+    };
+
+    fn load(source: &str) -> Result<Machine<i32>, Error<ParseIntError>> {
+        from_str(source, &|value| value.parse())
+    }
+
+    fn run(mut machine: Machine<i32>) -> Vec<i32> {
+        let mut outputs = Vec::new();
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.step() {
+                outputs.extend(output.value().copied());
+            }
+        }
+        outputs
+    }
+
+    #[test]
+    fn shebang() {
+        let machine = load("#!/usr/bin/env msc\ns 0 0 5\n.p").unwrap();
+
+        // If the shebang was a body line, the pointer would start on
+        // an empty line and stop immediately
+        assert_eq!(run(machine), [5]);
+    }
+
+    #[test]
+    fn shebang_after_first_line() {
+        // A '#!' after the first line is a comment
+        let machine = load("s 0 0 5\n#!/usr/bin/env msc\n.p").unwrap();
+
+        assert_eq!(run(machine), [5]);
+    }
+
+    #[test]
+    fn stack_lines_concatenate() {
+        let machine = load("s 0 0 1 2\ns 0 0 3\n.p.p").unwrap();
+
+        assert_eq!(run(machine), [3, 2]);
+    }
+
+    #[test]
+    fn capacity() {
+        let source = "C 0 0 16 # Comment\nC 1 0 4\ns 0 0 1 2\n.p      ";
+
+        let mut parser = Parser::new(&|value: &str| value.parse::<i32>());
+        for line in source.lines() {
+            parser.feed_line(line).unwrap();
+        }
+        assert_eq!(parser.capacities(), [(0, 0, 16), (1, 0, 4)]);
+
+        // Capacity lines do not change the program
+        assert_eq!(run(parser.finish().unwrap()), [2]);
+    }
+
+    #[test]
+    fn capacity_huge() {
+        let source = format!("C 0 0 {}\nC 1 0 1000000000000\n.p      ", usize::MAX);
+
+        // The capacities are only hints, so are not all allocated
+        assert_eq!(run(load(&source).unwrap()), [0]);
+    }
+
+    #[test]
+    fn capacity_errors() {
+        assert!(matches!(load("C 0 0"), Err(Error::MissingCapacity(_))));
+        assert!(matches!(load("C 0 0 x"), Err(Error::InvalidCoordinate(_))));
+        assert!(matches!(
+            load("C 1 0 4\n>"),
+            Err(Error::StackPointerOutOfRange((1, 0), (1, 1)))
+        ));
+    }
+
+    #[test]
+    fn stack_out_of_range_message() {
+        let err = load("s 5 2 1\n.....\n\n\n\n.").err().unwrap();
+
+        assert!(matches!(err, Error::StackPointerOutOfRange((5, 2), (2, 2))));
+        assert_eq!(
+            err.to_string(),
+            "stack pointer out of range: (5, 2); valid range is 0..2 × 0..2"
+        );
+    }
+
+    #[test]
+    fn stacks_only() {
+        let machine = load("s 0 0 5\ns 2 1 3 4\nC 1 0 8").unwrap();
+
+        assert_eq!(machine.stacks().width(), 3);
+        assert_eq!(machine.stacks().height(), 2);
+        assert!(machine.stacks().get((2, 1)).unwrap().iter().eq(&[3, 4]));
+        assert_eq!(machine.nonempty_stacks(), [(0, 0), (2, 1)]);
+        // There are no instructions to run
+        assert_eq!(run(machine), []);
+    }
+
+    #[test]
+    fn stacks_only_too_large() {
+        assert!(matches!(
+            load("s 4000000000 4000000000 1"),
+            Err(Error::StackPointerOutOfRange(
+                (4_000_000_000, 4_000_000_000),
+                (0, 0)
+            ))
+        ));
+
+        // The plane is still fitted to the stacks in range
+        assert!(matches!(
+            load("s 2 1 3\ns 0 70000 1"),
+            Err(Error::StackPointerOutOfRange((0, 70_000), (3, 2)))
+        ));
+    }
+
+    /// A number parse error that only implements `Display`
+    #[derive(Debug)]
+    struct NotANumber;
+
+    impl Display for NotANumber {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "not a number")
+        }
+    }
+
+    #[test]
+    fn display_only_parse_error() {
+        let parse = |value: &str| value.parse::<i32>().map_err(|_| NotANumber);
+
+        let machine = from_str("s 0 0 5\n.p", &parse).unwrap();
+        assert_eq!(run(machine), [5]);
+
+        let err = from_str("s 0 0 x\n.p", &parse).err().unwrap();
+        assert!(matches!(err, Error::InvalidNumber(NotANumber)));
+        assert_eq!(err.to_string(), "not a number");
+    }
+
+    fn load_with(source: &str, mode: Mode) -> Result<Machine<i32>, Error<ParseIntError>> {
+        from_str_with_options(source, &|value| value.parse(), LoadOptions { mode })
+    }
+
+    #[test]
+    fn strict() {
+        let position = |source| match load_with(source, Mode::Strict) {
+            Err(Error::At(SourcePosition { line, column }, err)) => ((line, column), *err),
+            result => panic!("Unexpected result: {:?}", result.err()),
         };
 
-        // Concatenate the stacks
-        stack.extend(new_stack);
+        assert!(matches!(
+            position("s 0 0 1\n.pq"),
+            ((1, 2), Error::InvalidInstruction(_))
+        ));
+        // A control character in a stack number
+        assert!(matches!(
+            position("s 0 0 1\u{7} 2\n.p"),
+            ((0, 6), Error::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            position("s x 0 5\n.p"),
+            ((0, 2), Error::InvalidCoordinate(_))
+        ));
+        assert!(matches!(
+            position("C 0 0 4 5\n.p"),
+            ((0, 8), Error::StrayToken(token)) if token == "5"
+        ));
+
+        // Positions are dropped when converting
+        assert!(matches!(
+            build::Error::from(load_with("q", Mode::Strict).err().unwrap()),
+            build::Error::InvalidInstruction(_)
+        ));
     }
 
-    Ok(stacks)
+    #[test]
+    fn strict_unicode_column() {
+        // Accept a non-ASCII number so that it can come before an error
+        let parse = |token: &str| {
+            if token == "\u{3C0}" {
+                Ok(3)
+            } else {
+                token.parse::<i32>()
+            }
+        };
+        let source = "s 0 0 \u{3C0} x # \u{e9}\n.p";
+        let result = from_str_with_options(source, &parse, LoadOptions { mode: Mode::Strict });
+
+        let Err(Error::At(position, err)) = result else {
+            panic!("Unexpected result: {:?}", result.err());
+        };
+        assert!(matches!(*err, Error::InvalidNumber(_)));
+        // The column is the char index, which is before the byte offset
+        assert_eq!(position, SourcePosition { line: 0, column: 8 });
+        assert_eq!(
+            position.byte_offset(source.lines().next().unwrap()),
+            Some(9)
+        );
+    }
+
+    #[test]
+    fn lenient() {
+        // The unknown instruction is loaded as a space and the malformed
+        // number is skipped
+        let machine = load_with("s 0 0 1\u{7} 2\n.pq", Mode::Lenient).unwrap();
+        assert_eq!(run(machine), [2]);
+
+        // Header lines with malformed coordinates are ignored
+        let machine = load_with("s x 0 5\nC 0 0\ns 0 0 3\n.p", Mode::Lenient).unwrap();
+        assert_eq!(run(machine), [3]);
+
+        // Stacks still have to be in range
+        assert!(matches!(
+            load_with("s 1 0 5\n.p", Mode::Lenient),
+            Err(Error::StackPointerOutOfRange((1, 0), (1, 1)))
+        ));
+    }
+
+    #[test]
+    fn default_options() {
+        let parse = |value: &str| value.parse::<i32>();
+        let with_default = |source| from_str_with_options(source, &parse, LoadOptions::default());
+
+        let machine = with_default("s 0 0 1 2\n.pp").unwrap();
+        assert_eq!(run(machine), run(load("s 0 0 1 2\n.pp").unwrap()));
+
+        // Anything that cannot be parsed is still an error, without a position
+        for source in ["s 0 0 1\n.pq", "s 0 0 x\n.p", "s x 0 5\n.p"] {
+            assert_eq!(
+                with_default(source).err().map(|err| err.to_string()),
+                load(source).err().map(|err| err.to_string()),
+            );
+            assert!(!matches!(with_default(source), Ok(_) | Err(Error::At(..))));
+        }
+    }
+
+    #[test]
+    fn extended_numbers() {
+        let parse = |value| parse_n_extended::<i32>(value).ok();
+
+        assert_eq!(parse("1_000"), Some(1000));
+        assert_eq!(parse("0xFF"), Some(255));
+        assert_eq!(parse("0b1010"), Some(10));
+        assert_eq!(parse("-0x1_0"), Some(-16));
+        assert_eq!(parse("+12"), Some(12));
+
+        for malformed in [
+            "", "_1", "1_", "1__0", "0x", "0xG", "0x+5", "0b2", "--1", "1.5",
+        ] {
+            assert_eq!(parse(malformed), None, "parsed {malformed:?}");
+        }
+
+        for (malformed, error) in [
+            ("1__0", "misplaced separator or sign"),
+            ("0xG", "invalid digit"),
+        ] {
+            let err = parse_n_extended::<i32>(malformed).unwrap_err();
+            assert!(err.to_string().starts_with(error), "{malformed:?}: {err}");
+        }
+        assert!(matches!(
+            parse_n_extended::<i32>("0x+5"),
+            Err(ParseExtendedError::Malformed)
+        ));
+        assert!(matches!(
+            parse_n_extended::<i32>("0b2"),
+            Err(ParseExtendedError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn extended_numbers_load() {
+        let machine = from_str("s 0 0 0x10 1_000\n.p.p", &parse_n_extended::<i32>).unwrap();
+
+        assert_eq!(run(machine), [1000, 16]);
+    }
+
+    #[test]
+    fn into_build_error() {
+        let convert = |source| build::Error::from(load(source).err().unwrap());
+
+        assert!(matches!(
+            convert("s 0 x"),
+            build::Error::InvalidCoordinate(_)
+        ));
+        assert!(matches!(convert("s 0 0 x"), build::Error::InvalidNumber(_)));
+        assert!(matches!(convert("s 0"), build::Error::MissingStackPointer));
+        assert!(matches!(convert("C 0 0"), build::Error::MissingCapacity));
+        assert!(matches!(
+            convert("s 1 0\n>"),
+            build::Error::StackPointerOutOfRange((1, 0))
+        ));
+        assert!(matches!(convert("q"), build::Error::InvalidInstruction(_)));
+    }
+
+    /// A reader that fails after reading its source
+    struct FailingReader<'a>(&'a [u8]);
+
+    impl Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("read failed"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn reader_error() {
+        let reader = BufReader::new(FailingReader(b"s 0 0 5\n.p\n"));
+        let result = from_reader(reader, &|value: &str| value.parse::<i32>());
+
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn reader_invalid_utf8() {
+        let result = from_reader(&b".p\n\xff\n.p"[..], &|value: &str| value.parse::<i32>());
+
+        assert!(matches!(
+            result,
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn dir() {
+        let dir = env::temp_dir().join(format!("msc-load-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("valid.msc"), "s 0 0 5\n.p\n").unwrap();
+        fs::write(dir.join("invalid.msc"), ".p\n#\n_").unwrap();
+        fs::write(dir.join("ignored.txt"), "_").unwrap();
+
+        let results = from_dir(&dir, &|value: &str| value.parse::<i32>());
+        fs::remove_dir_all(&dir).unwrap();
+        let mut results = results.unwrap().into_iter();
+
+        let (path, result) = results.next().unwrap();
+        assert_eq!(path, dir.join("invalid.msc"));
+        assert!(matches!(result, Err(Error::InvalidInstruction(_))));
+
+        let (path, result) = results.next().unwrap();
+        assert_eq!(path, dir.join("valid.msc"));
+        assert_eq!(run(result.unwrap()), [5]);
+
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    fn dir_missing() {
+        let dir = env::temp_dir().join(format!("msc-load-dir-missing-{}", std::process::id()));
+
+        assert!(from_dir(&dir, &|value: &str| value.parse::<i32>()).is_err());
+    }
+
+    #[test]
+    fn include() {
+        let dir = env::temp_dir().join(format!("msc-load-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("main.msc"),
+            "s 0 0 5\n.v\ninclude \"lib/print.msc\"\n p",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/print.msc"), "#!/usr/bin/env msc\ns 0 0 3\n +").unwrap();
+
+        let machine = from_file(dir.join("main.msc"), &|value: &str| value.parse::<i32>());
+        let source = fs::read_to_string(dir.join("main.msc")).unwrap();
+        let from_source =
+            from_str_with_includes(&source, &dir, &|value: &str| value.parse::<i32>());
+        fs::remove_dir_all(&dir).unwrap();
+        let machine = machine.unwrap();
+
+        // The included row is between the rows of the including file
+        assert_eq!(machine.instructions().height(), 3);
+        assert_eq!(
+            machine.source_position((1, 1)),
+            Some(SourcePosition { line: 2, column: 1 })
+        );
+        assert_eq!(
+            machine.source_position((1, 2)),
+            Some(SourcePosition { line: 3, column: 1 })
+        );
+        assert_eq!(run(machine), [8]);
+        assert_eq!(run(from_source.unwrap()), [8]);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = env::temp_dir().join(format!("msc-load-include-cycle-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.msc"), ".p\ninclude \"b.msc\"").unwrap();
+        fs::write(dir.join("b.msc"), "include \"a.msc\"").unwrap();
+        fs::write(dir.join("self.msc"), "include \"self.msc\"").unwrap();
+
+        let cycle = from_file(dir.join("a.msc"), &|value: &str| value.parse::<i32>());
+        let from_source = from_str_with_includes("include \"self.msc\"", &dir, &|value: &str| {
+            value.parse::<i32>()
+        });
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(cycle, Err(Error::IncludeCycle(path)) if path.ends_with("a.msc")));
+        assert!(
+            matches!(from_source, Err(Error::IncludeCycle(path)) if path.ends_with("self.msc"))
+        );
+    }
+
+    #[test]
+    fn include_too_deep() {
+        let dir = env::temp_dir().join(format!("msc-load-include-deep-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Each file includes the next, down to the last
+        for depth in 0..MAX_INCLUDE_DEPTH {
+            fs::write(
+                dir.join(format!("{depth}.msc")),
+                format!("include \"{}.msc\"", depth + 1),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join(format!("{MAX_INCLUDE_DEPTH}.msc")), "s 0 0 7\n.p").unwrap();
+        fs::write(dir.join("top.msc"), "include \"0.msc\"").unwrap();
+
+        let parse = |value: &str| value.parse::<i32>();
+        // Including files 1 to MAX_INCLUDE_DEPTH is allowed both ways
+        let file = from_file(dir.join("0.msc"), &parse);
+        let source = from_str_with_includes("include \"1.msc\"", &dir, &parse);
+        // But one more is too deep, both ways
+        let deep_file = from_file(dir.join("top.msc"), &parse);
+        let deep_source = from_str_with_includes("include \"0.msc\"", &dir, &parse);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(run(file.unwrap()), [7]);
+        assert_eq!(run(source.unwrap()), [7]);
+        assert!(matches!(deep_file, Err(Error::IncludeTooDeep(_))));
+        assert!(matches!(deep_source, Err(Error::IncludeTooDeep(_))));
+    }
+
+    #[test]
+    fn include_missing() {
+        let result = from_str_with_includes(
+            "include \"missing.msc\"",
+            env::temp_dir(),
+            &|value: &str| value.parse::<i32>(),
+        );
+
+        assert!(matches!(result, Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn minimize_padded() {
+        let source = "#!/usr/bin/env msc\n# Print 5\ns 0 0 5\nC 0 0 8\n.+ p  # print\n d  ";
+        let minimized = minimize(source, &|value| value.parse::<i32>(), 100, |machine| {
+            run(machine) == [5]
+        });
+
+        // Adding the 5 to the empty register is shorter than popping it
+        assert_eq!(minimized, "s 0 0 5\n + p");
+    }
+
+    #[test]
+    fn minimize_failing() {
+        let source = "s 0 0 5\n.p";
+        let minimized = minimize(source, &|value| value.parse::<i32>(), 100, |machine| {
+            run(machine) == [6]
+        });
+
+        assert_eq!(minimized, source);
+    }
+
+    #[test]
+    fn reader() {
+        let machine = from_reader(&b"s 0 0 5\n.p\n"[..], &|value: &str| value.parse::<i32>());
+
+        assert_eq!(run(machine.unwrap()), [5]);
+    }
+
+    #[test]
+    fn parse_lines() {
+        let parse = |value: &str| value.parse::<i32>();
+        let (mut instructions, mut stacks, mut capacities) = (Vec::new(), Vec::new(), Vec::new());
+
+        parse_line("s 0 0 5", &mut instructions, &mut stacks, &parse).unwrap();
+        parse_line("C 0 0 8", &mut instructions, &mut stacks, &parse).unwrap();
+        parse_line_with_capacities(
+            "C 0 0 16",
+            &mut instructions,
+            &mut stacks,
+            &mut capacities,
+            &parse,
+        )
+        .unwrap();
+        parse_line(".p", &mut instructions, &mut stacks, &parse).unwrap();
+
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(stacks, [(0, 0, vec![5])]);
+        assert_eq!(capacities, [(0, 0, 16)]);
+    }
+
+    #[test]
+    fn parser() {
+        let source = "#!/usr/bin/env msc\ns 0 0 2 3\n# Comment\n.v\n p+p";
+
+        let mut parser = Parser::new(&|value: &str| value.parse::<i32>());
+        for line in source.split('\n') {
+            parser.feed_line(line).unwrap();
+        }
+        let mut machine = parser.finish().unwrap();
+        let mut expected = load(source).unwrap();
+
+        assert_eq!(machine.snapshot(), expected.snapshot());
+        assert_eq!(machine.instruction_count(), expected.instruction_count());
+        assert_eq!(
+            machine.source_position((1, 1)),
+            expected.source_position((1, 1))
+        );
+        assert_eq!(machine.run_collect(), expected.run_collect());
+    }
+
+    /// A plane with a huge width and the given height, that has no items
+    struct WidePlane(usize);
+
+    impl Plane for WidePlane {
+        type Item = Instruction;
+
+        fn width(&self) -> usize {
+            usize::MAX
+        }
+
+        fn height(&self) -> usize {
+            self.0
+        }
+
+        fn get(&self, _pointer: Pointer) -> Option<&Instruction> {
+            None
+        }
+
+        fn get_mut(&mut self, _pointer: Pointer) -> Option<&mut Instruction> {
+            None
+        }
+    }
+
+    #[test]
+    fn create_stacks_huge_width() {
+        let stacks = create_stacks::<i32, ParseIntError>(Vec::new(), &[], &WidePlane(0)).unwrap();
+
+        assert_eq!(stacks.height(), 0);
+
+        // With rows, the width is rounded up without overflowing
+        let result = create_stacks::<i32, ParseIntError>(vec![(0, 2, vec![1])], &[], &WidePlane(5));
+        assert!(matches!(
+            result,
+            Err(Error::StackPointerOutOfRange((0, 2), (width, 2))) if width == usize::MAX / 4 + 1
+        ));
+    }
 }
@@ -1,19 +1,300 @@
 //! The `MSCode` machine that runs `MSCode`
 
+use core::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    iter,
+};
+
+#[cfg(feature = "std")]
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+#[cfg(feature = "std")]
+use crate::plane::VecPlane;
+#[cfg(feature = "std")]
+use crate::velocity_name;
 use crate::{
-    add_velocity_to_pointer, instruction::Instruction, plane::Plane, stack::Stack, Number, Pointer,
-    Velocity,
+    add_velocity_to_pointer,
+    instruction::Instruction,
+    io::{self, Input, InputKind, Output},
+    operator::{self, REGISTER_COUNT},
+    plane::{self, Plane},
+    program::Program,
+    rng::Xorshift64,
+    stack::Stack,
+    velocity_to_arrow, Direction, Number, Pointer, SourcePosition, Velocity,
 };
 
 /// The machine state
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub enum State {
     #[default]
     Running,
     Stopped,
     InputWaiting,
+    /// The machine was stopped early, see [`HaltReason`]
+    Halted {
+        reason: HaltReason,
+    },
+}
+
+/// Why a machine was halted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HaltReason {
+    /// The step limit was reached
+    StepLimit,
+    /// A recent state was repeated, see [`Machine::set_livelock_detection`]
+    Livelock,
+    /// The output limit was reached, see [`Machine::with_output_limit`]
+    OutputLimit,
+}
+
+impl Display for HaltReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use HaltReason::{Livelock, OutputLimit, StepLimit};
+        match self {
+            StepLimit => write!(f, "step limit reached"),
+            Livelock => write!(f, "livelock detected"),
+            OutputLimit => write!(f, "output limit reached"),
+        }
+    }
+}
+
+/// What the machine does when the pointer moves off of an edge
+/// of the instructions
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum EdgeBehavior {
+    /// Stop the machine
+    #[default]
+    Stop,
+    /// Wrap the pointer around to the opposite edge
+    Wrap,
+}
+
+/// What the [`Duplicate`](operator::Operator::Duplicate) operator does
+/// when the stack is empty
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum DuplicatePolicy {
+    /// Push a zero, as if the stack had a zero on top
+    #[default]
+    PushZero,
+    /// Leave the stack empty
+    NoOp,
+}
+
+/// What the machine does when the pointer is over a stack that is not
+/// in the stack plane
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum MissingStackPolicy {
+    /// Panic, even in [`Machine::try_step`]
+    Panic,
+    /// Return [`StepError::StackOutOfRange`] from [`Machine::try_step`],
+    /// or panic in [`Machine::step`]
+    #[default]
+    Error,
+    /// Use an empty stack, so that pops give defaults and pushes are
+    /// discarded
+    Empty,
+}
+
+/// Errors from running an iteration on the machine
+#[derive(Clone, Copy, Debug)]
+pub enum StepError {
+    /// The pointer is over a stack that is not in the stack plane.
+    /// `position` is where the instruction came from in the source,
+    /// if the machine knows it
+    StackOutOfRange {
+        stack: Pointer,
+        position: Option<SourcePosition>,
+    },
+}
+
+#[cfg(feature = "std")]
+impl Error for StepError {}
+
+impl Display for StepError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use StepError::StackOutOfRange;
+        match self {
+            StackOutOfRange {
+                stack,
+                position: Some(position),
+            } => write!(f, "stack pointer out of range at {position}: {stack:?}"),
+            StackOutOfRange {
+                stack,
+                position: None,
+            } => write!(f, "stack pointer out of range: {stack:?}"),
+        }
+    }
+}
+
+/// What happened in a step, see [`Machine::step_detailed`]
+#[derive(Clone, Copy)]
+pub struct StepInfo<'a, N> {
+    /// The instruction that ran, or `None` if the step did not run one,
+    /// such as when the machine is not running
+    pub instruction: Option<Instruction>,
+    /// The pointer before the step
+    pub pointer: Pointer,
+    /// The output of the step
+    pub output: Option<Output<'a, N>>,
+}
+
+/// An instruction that ran, with the velocity and register after it
+/// ran, see [`Machine::trace`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TraceRecord<N> {
+    /// Where the instruction was
+    pub pointer: Pointer,
+    pub instruction: char,
+    pub velocity: Velocity,
+    pub register: N,
+}
+
+/// Write a trace as a JSON array of objects, without any dependencies.
+///
+/// Each record is written as
+/// `{"pointer":[x,y],"instruction":"c","velocity":v,"register":r}`,
+/// so the register must display as a JSON number
+#[cfg(feature = "std")]
+#[must_use]
+pub fn trace_to_json<N: Display>(records: &[TraceRecord<N>]) -> String {
+    let records: Vec<String> = records
+        .iter()
+        .map(|record| {
+            let instruction = match record.instruction {
+                '"' => "\\\"".to_owned(),
+                '\\' => "\\\\".to_owned(),
+                instruction => instruction.to_string(),
+            };
+            format!(
+                "{{\"pointer\":[{},{}],\"instruction\":\"{instruction}\",\"velocity\":{},\"register\":{}}}",
+                record.pointer.0, record.pointer.1, record.velocity, record.register
+            )
+        })
+        .collect();
+    format!("[{}]", records.join(","))
+}
+
+/// Load a machine from rows of instruction characters, with empty stacks
+/// sized to fit the instructions.
+///
+/// Unlike [`load::from_str`](crate::load::from_str), every character is
+/// an instruction, so the rows cannot have headers or comments.
+/// Each row is given the source line of its index
+///
+/// # Errors
+/// - [`load::Error::InvalidInstruction`](crate::load::Error::InvalidInstruction) - failed to parse a character as an instruction
+///
+/// # Examples
+/// ```
+/// use msc::machine::{from_char_grid, State};
+///
+/// let mut machine = from_char_grid::<i32>(&[">.+v", "   p"]).unwrap();
+///
+/// assert_eq!(machine.run_collect(), (vec![0], State::Stopped));
+/// ```
+#[cfg(feature = "std")]
+pub fn from_char_grid<N: Number>(
+    rows: &[&str],
+) -> Result<crate::load::Machine<N>, crate::load::Error<core::convert::Infallible>> {
+    use crate::load;
+
+    let instructions: VecPlane<Instruction> = rows
+        .iter()
+        .map(|row| row.chars().map(Instruction::try_from).collect())
+        .collect::<Result<Vec<Vec<Instruction>>, _>>()?
+        .into();
+    let stacks = load::create_stacks(Vec::new(), &[], &instructions)?;
+    let lines: Vec<usize> = (0..rows.len()).collect();
+    let positions = load::create_positions(&lines, instructions.width());
+
+    Ok(Machine::new(instructions, stacks).with_source_positions(positions))
+}
+
+/// A snapshot of the mutable state of a [`Machine`].
+/// This does not include the instructions
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot<N, StackPlane> {
+    state: State,
+    stacks: StackPlane,
+    register: N,
+    flag: N,
+    pointer: Pointer,
+    velocity: Velocity,
+    steps: usize,
+    outputs: usize,
+    input_kind: InputKind,
+    input_pointer: Pointer,
+    register_file: Option<[N; REGISTER_COUNT]>,
+    selected_register: usize,
+    rng: Xorshift64,
+}
+
+/// The number of recent states kept for livelock detection
+const HISTORY_LENGTH: usize = 64;
+
+/// A ring of recent state hashes for livelock detection
+#[derive(Clone, Copy, Debug)]
+struct History {
+    hashes: [Option<u64>; HISTORY_LENGTH],
+    next: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            hashes: [None; HISTORY_LENGTH],
+            next: 0,
+        }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.hashes.contains(&Some(hash))
+    }
+
+    const fn push(&mut self, hash: u64) {
+        self.hashes[self.next] = Some(hash);
+        self.next = (self.next + 1) % HISTORY_LENGTH;
+    }
+}
+
+/// A 64-bit FNV-1a hasher, as `core` does not provide a hasher
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A callback for state changes, see [`Machine::on_state_change`]
+enum StateCallback {
+    #[cfg(feature = "std")]
+    Boxed(Box<dyn FnMut(State, State) + Send>),
+    Fn(fn(State, State)),
 }
 
+/// The coordinates popped by a jump, which are `None` if they do not fit
+/// in a `usize`
+type JumpTarget = (Option<usize>, Option<usize>);
+
 /// The `MSCode` runner
 pub struct Machine<N, InstructionPlane, StackType, StackPlane>
 where
@@ -26,8 +307,45 @@ where
     instructions: InstructionPlane,
     stacks: StackPlane,
     register: N,
+    flag: N,
     pointer: Pointer,
     velocity: Velocity,
+    steps: usize,
+    /// The number of values printed
+    outputs: usize,
+    /// The kind of input to read when in the `InputWaiting` state
+    input_kind: InputKind,
+    /// The position of the last [`InputAll`](io::IO::InputAll)
+    /// instruction, whose stack the inputs are pushed onto
+    input_pointer: Pointer,
+    step_limit: Option<usize>,
+    output_limit: Option<usize>,
+    /// Whether runs of spaces are skipped in a single step
+    fast_forward: bool,
+    /// Hashes the state for livelock detection, if it is enabled
+    state_hash: Option<fn(&Self) -> u64>,
+    state_callback: Option<StateCallback>,
+    history: History,
+    edge_behavior: EdgeBehavior,
+    duplicate_policy: DuplicatePolicy,
+    /// The number of values popped from empty stacks, if tracked
+    underflows: Option<usize>,
+    missing_stack_policy: MissingStackPolicy,
+    /// The stack used for missing stacks with [`MissingStackPolicy::Empty`]
+    null_stack: Option<StackType>,
+    /// The extra registers, if the register file is enabled
+    register_file: Option<[N; REGISTER_COUNT]>,
+    selected_register: usize,
+    /// The seed of `rng`, kept for resetting
+    seed: u64,
+    rng: Xorshift64,
+    #[cfg(feature = "std")]
+    positions: Option<VecPlane<SourcePosition>>,
+    /// Whether the positions of instructions that run are recorded
+    #[cfg(feature = "std")]
+    coverage: bool,
+    #[cfg(feature = "std")]
+    executed: HashSet<Pointer>,
 }
 
 impl<N, InstructionPlane, StackType, StackPlane> Machine<N, InstructionPlane, StackType, StackPlane>
@@ -45,67 +363,585 @@ where
             instructions,
             stacks,
             register: N::ZERO,
+            flag: N::ZERO,
             pointer: Pointer::default(),
             velocity: Velocity::default(),
+            steps: 0,
+            outputs: 0,
+            input_kind: InputKind::default(),
+            input_pointer: Pointer::default(),
+            step_limit: None,
+            output_limit: None,
+            fast_forward: false,
+            state_hash: None,
+            state_callback: None,
+            history: History::new(),
+            edge_behavior: EdgeBehavior::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            underflows: None,
+            missing_stack_policy: MissingStackPolicy::default(),
+            null_stack: None,
+            register_file: None,
+            selected_register: 0,
+            seed: 0,
+            rng: Xorshift64::new(0),
+            #[cfg(feature = "std")]
+            positions: None,
+            #[cfg(feature = "std")]
+            coverage: false,
+            #[cfg(feature = "std")]
+            executed: HashSet::new(),
+        }
+    }
+
+    /// Create a new machine from instructions and stacks, starting
+    /// with the given register, pointer and velocity.
+    /// If the pointer is outside of the instructions, the machine
+    /// stops on its first step
+    #[must_use]
+    pub fn with_state(
+        instructions: InstructionPlane,
+        stacks: StackPlane,
+        register: N,
+        pointer: Pointer,
+        velocity: Velocity,
+    ) -> Self {
+        Self {
+            register,
+            pointer,
+            velocity,
+            ..Self::new(instructions, stacks)
+        }
+    }
+
+    /// Attach the source position of each instruction, which is
+    /// included in errors
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_source_positions(mut self, positions: VecPlane<SourcePosition>) -> Self {
+        self.positions = Some(positions);
+        self
+    }
+
+    /// Get the source position of the instruction at `pointer`, if known
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn source_position(&self, pointer: Pointer) -> Option<SourcePosition> {
+        self.positions.as_ref()?.get(pointer).copied()
+    }
+
+    /// Start recording the positions of the instructions that run,
+    /// including spaces. If coverage is already enabled, the recorded
+    /// positions are kept
+    #[cfg(feature = "std")]
+    pub const fn enable_coverage(&mut self) {
+        self.coverage = true;
+    }
+
+    /// Get the positions of the instructions that have run since
+    /// [`Machine::enable_coverage`], which is empty if it is not enabled
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub const fn executed_cells(&self) -> &HashSet<Pointer> {
+        &self.executed
+    }
+
+    /// Record that the instruction under the pointer has run,
+    /// if coverage is enabled
+    #[cfg(feature = "std")]
+    #[inline]
+    fn record_coverage(&mut self) {
+        if self.coverage {
+            self.executed.insert(self.pointer);
         }
     }
 
     /// Run an iteration on the machine
-    pub fn step(&mut self) -> Option<&N> {
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane,
+    /// use [`Machine::try_step`] to handle this
+    pub fn step(&mut self) -> Option<Output<'_, N>> {
+        match self.step_internal(None) {
+            Ok(output) => output,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Run an iteration on the machine like [`Machine::step`], also
+    /// getting the instruction that ran and where it was
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane,
+    /// use [`Machine::try_step`] to handle this
+    pub fn step_detailed(&mut self) -> StepInfo<'_, N> {
+        let (pointer, steps) = (self.pointer, self.steps);
+        let instruction = self.instructions.get(pointer).copied();
+        let printed = self.step().is_some();
+
+        // The step count only changes if an instruction ran
+        let instruction = instruction.filter(|_| self.steps != steps);
+        StepInfo {
+            instruction,
+            pointer,
+            output: instruction
+                .filter(|_| printed)
+                .map(|instruction| self.output(instruction)),
+        }
+    }
+
+    /// Run an iteration on the machine, reading from `input` rather
+    /// than entering the `InputWaiting` state.
+    /// If `input` has nothing to read, the machine will wait as usual
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane,
+    /// use [`Machine::try_step_with_input`] to handle this
+    pub fn step_with_input<I: Input<N>>(&mut self, input: &mut I) -> Option<Output<'_, N>> {
+        match self.try_step_with_input(input) {
+            Ok(output) => output,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Run an iteration on the machine without panicking.
+    /// If an error is returned, the machine is left unchanged
+    ///
+    /// # Errors
+    /// - [`StepError::StackOutOfRange`] - the pointer is over a stack that is not in the stack plane
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    /// and the [`MissingStackPolicy`] is [`Panic`](MissingStackPolicy::Panic)
+    pub fn try_step(&mut self) -> Result<Option<Output<'_, N>>, StepError> {
+        self.step_internal(None)
+    }
+
+    /// Run an iteration on the machine without panicking, reading from
+    /// `input` rather than entering the `InputWaiting` state.
+    /// If an error is returned, the machine is left unchanged
+    ///
+    /// # Errors
+    /// - [`StepError::StackOutOfRange`] - the pointer is over a stack that is not in the stack plane
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    /// and the [`MissingStackPolicy`] is [`Panic`](MissingStackPolicy::Panic)
+    pub fn try_step_with_input<I: Input<N>>(
+        &mut self,
+        input: &mut I,
+    ) -> Result<Option<Output<'_, N>>, StepError> {
+        if matches!(self.state, State::InputWaiting) {
+            let Some(value) = input.read_kind(self.input_kind) else {
+                return Ok(None);
+            };
+            if self.input_kind == InputKind::All {
+                self.feed_all(iter::once(value).chain(iter::from_fn(|| input.read_more())));
+            } else {
+                self.input(value);
+            }
+        }
+
+        self.step_internal(Some(input))
+    }
+
+    #[inline]
+    fn step_internal(
+        &mut self,
+        input: Option<&mut dyn Input<N>>,
+    ) -> Result<Option<Output<'_, N>>, StepError> {
         if !matches!(self.state, State::Running) {
-            return None;
+            return Ok(None);
+        }
+
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            self.set_state(State::Halted {
+                reason: HaltReason::StepLimit,
+            });
+            return Ok(None);
+        }
+
+        let hash = self.state_hash.map(|state_hash| state_hash(self));
+        if hash.is_some_and(|hash| self.history.contains(hash)) {
+            self.set_state(State::Halted {
+                reason: HaltReason::Livelock,
+            });
+            return Ok(None);
         }
 
-        let Some(instruction) = self.instructions.get(self.pointer) else {
-            self.state = State::Stopped;
-            return None;
+        let Some(&instruction) = self.instructions.get(self.pointer) else {
+            self.set_state(State::Stopped);
+            return Ok(None);
         };
 
-        let output = {
+        let prints = matches!(
+            instruction,
+            Instruction::IO(io::IO::Print | io::IO::PrintChar | io::IO::PrintErr)
+        );
+        if prints && self.output_limit.is_some_and(|limit| self.outputs >= limit) {
+            self.set_state(State::Halted {
+                reason: HaltReason::OutputLimit,
+            });
+            return Ok(None);
+        }
+
+        let mut input_kind = None;
+        let mut jump = None;
+        // The current stack is looked up only by the arms that use it, so it
+        // is already found at most once per step. Looking it up before the
+        // match would add a lookup for the instructions that do not use it
+        let print = {
             use Instruction::{Comparator, Deflector, Operator, Space, IO};
             match instruction {
-                Space => None,
+                Space => false,
                 Deflector(deflector) => {
-                    self.velocity = deflector.apply(self.velocity);
-                    None
+                    self.velocity = deflector.apply_conditional(self.velocity, &self.register);
+                    false
                 }
                 Operator(operation) => {
-                    let stack = self
-                        .stacks
-                        .get_mut((self.pointer.0 / 4, self.pointer.1 / 4))
-                        .expect("Stack pointer out of range!");
-
-                    self.register = operation.apply(self.register, stack);
-                    None
+                    jump = self.apply_operator(operation)?;
+                    false
                 }
                 Comparator(comparator) => {
-                    let stack = self
-                        .stacks
-                        .get_mut((self.pointer.0 / 4, self.pointer.1 / 4))
-                        .expect("Stack pointer out of range!");
+                    self.count_underflows(comparator.pop_count());
+                    let (register, velocity) = (self.register, self.velocity);
+                    let stack = self.current_stack_mut()?;
 
-                    self.velocity = comparator.apply(&self.register, stack, self.velocity);
-                    None
+                    self.velocity = comparator.apply(&register, stack, velocity);
+                    false
                 }
                 IO(io) => {
-                    let (output, io_wait) = io.apply(&self.register);
-                    if io_wait {
-                        self.state = State::InputWaiting;
+                    let (output, kind) = io.apply(&self.register);
+                    let print = output.is_some() || matches!(io, io::IO::Flush);
+                    if kind == Some(InputKind::All) {
+                        // Check the stack now, so that pushing the inputs cannot fail
+                        self.current_stack_mut()?;
+                        self.input_pointer = self.pointer;
                     }
-                    output
+                    input_kind = kind;
+                    print
+                }
+            }
+        };
+
+        if let Some(kind) = input_kind {
+            // Only wait if the input source has nothing to read
+            match input.and_then(|input| Some((input.read_kind(kind)?, input))) {
+                Some((value, input)) if kind == InputKind::All => {
+                    self.push_inputs(iter::once(value).chain(iter::from_fn(|| input.read_more())));
+                }
+                Some((value, _)) => self.register = value,
+                None => {
+                    self.set_state(State::InputWaiting);
+                    self.input_kind = kind;
+                }
+            }
+        }
+
+        self.steps = self.steps.saturating_add(1);
+        #[cfg(feature = "std")]
+        self.record_coverage();
+        if prints {
+            self.outputs = self.outputs.saturating_add(1);
+        }
+        if let Some(hash) = hash {
+            self.history.push(hash);
+        }
+
+        self.pointer = match jump {
+            // Jump to the target if it is in range
+            Some((Some(x), Some(y))) if self.instructions.in_bounds((x, y)) => (x, y),
+            // Otherwise, stop the machine
+            Some(_) => {
+                self.set_state(State::Stopped);
+                return Ok(None);
+            }
+            None => self.next_pointer(self.pointer, self.velocity),
+        };
+        // Livelock detection needs the state of every step
+        if self.fast_forward
+            && self.state_hash.is_none()
+            && matches!(instruction, Instruction::Space)
+        {
+            self.skip_spaces();
+        }
+        Ok(print.then(|| self.output(instruction)))
+    }
+
+    /// Move the pointer over the spaces ahead of it, counting each one
+    /// as a step.
+    /// This stops at the step limit, and after at most one lap of the
+    /// plane so that a blank line with wrapping edges cannot loop forever
+    fn skip_spaces(&mut self) {
+        let lap = self.instructions.width().max(self.instructions.height());
+        for _ in 0..lap {
+            if self.step_limit.is_some_and(|limit| self.steps >= limit)
+                || !matches!(
+                    self.instructions.get(self.pointer),
+                    Some(Instruction::Space)
+                )
+            {
+                break;
+            }
+            self.steps = self.steps.saturating_add(1);
+            #[cfg(feature = "std")]
+            self.record_coverage();
+            self.pointer = self.next_pointer(self.pointer, self.velocity);
+        }
+    }
+
+    /// Get the output of an IO instruction that has just run
+    const fn output(&self, instruction: Instruction) -> Output<'_, N> {
+        match instruction {
+            Instruction::IO(io::IO::PrintChar) => Output::Char(&self.register),
+            Instruction::IO(io::IO::PrintErr) => Output::Error(&self.register),
+            Instruction::IO(io::IO::Flush) => Output::Flush,
+            _ => Output::Number(&self.register),
+        }
+    }
+
+    /// Run an operator, returning the target if it is a jump.
+    /// Operators that use more than the register and stack are run here
+    #[inline]
+    fn apply_operator(
+        &mut self,
+        operation: operator::Operator,
+    ) -> Result<Option<JumpTarget>, StepError> {
+        use operator::Operator::{
+            Duplicate, Jump, LoadFlag, LoadRegister, PushX, PushY, Random, SelectRegister,
+            StoreFlag, StoreRegister,
+        };
+        self.count_underflows(operation.pop_count(self.register));
+        match operation {
+            Duplicate if matches!(self.duplicate_policy, DuplicatePolicy::NoOp) => {
+                let stack = self.current_stack_mut()?;
+                if let Some(value) = stack.pop() {
+                    stack.push(value);
+                    stack.push(value);
+                }
+            }
+            StoreFlag => self.flag = self.register,
+            LoadFlag => self.register = self.flag,
+            Random => {
+                let bound = self.current_stack_mut()?.pop();
+                let value = match bound.and_then(N::to_usize).filter(|&bound| bound > 0) {
+                    Some(bound) => self.rng.next_below(bound),
+                    None => self.rng.next_n(),
+                };
+                self.current_stack_mut()?.push(value);
+            }
+            // The register instructions do nothing without a register file
+            SelectRegister(register) => {
+                if self.register_file.is_some() {
+                    self.selected_register = register % REGISTER_COUNT;
+                }
+            }
+            StoreRegister => {
+                if let Some(register_file) = &mut self.register_file {
+                    register_file[self.selected_register] = self.register;
+                }
+            }
+            LoadRegister => {
+                if let Some(register_file) = &self.register_file {
+                    self.register = register_file[self.selected_register];
                 }
             }
+            PushX | PushY => {
+                let coordinate = if matches!(operation, PushX) {
+                    self.pointer.0
+                } else {
+                    self.pointer.1
+                };
+                self.current_stack_mut()?
+                    .push(N::from_usize_saturating(coordinate));
+            }
+            Jump => {
+                let stack = self.current_stack_mut()?;
+
+                let (x, y) = operator::Operator::pop_jump_target(stack);
+                return Ok(Some((x.to_usize(), y.to_usize())));
+            }
+            _ => {
+                let register = self.register;
+                let stack = self.current_stack_mut()?;
+
+                self.register = operation.apply(register, stack);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get where a pointer moves to with a velocity, following the
+    /// edge behavior
+    #[inline]
+    fn next_pointer(&self, pointer: Pointer, velocity: Velocity) -> Pointer {
+        let pointer = add_velocity_to_pointer(velocity, pointer);
+        match self.edge_behavior {
+            EdgeBehavior::Wrap if !self.instructions.in_bounds(pointer) => {
+                self.wrap_pointer(pointer)
+            }
+            _ => pointer,
+        }
+    }
+
+    /// Count the values that will be popped from the current stack but
+    /// are missing, if underflow tracking is enabled.
+    /// Nothing is counted if the stack is out of range and the step fails
+    fn count_underflows(&mut self, pops: usize) {
+        let Some(count) = self.underflows else {
+            return;
+        };
+        let len = match self.current_stack() {
+            Some(stack) => stack.len(),
+            None if matches!(self.missing_stack_policy, MissingStackPolicy::Empty) => 0,
+            None => return,
+        };
+        self.underflows = Some(count + pops.saturating_sub(len));
+    }
+
+    /// Wrap a pointer that has moved off of an edge to the opposite edge
+    fn wrap_pointer(&self, pointer: Pointer) -> Pointer {
+        let wrap = |value: usize, length: usize| match value {
+            _ if length == 0 => value,
+            // Moved off of the left or top edge
+            usize::MAX => length - 1,
+            _ => value % length,
         };
 
-        self.pointer = add_velocity_to_pointer(self.velocity, self.pointer);
-        output
+        (
+            wrap(pointer.0, self.instructions.width()),
+            wrap(pointer.1, self.instructions.height()),
+        )
     }
 
-    /// Provide input to the machine when in the `InputWaiting` state
+    /// Get the coordinates of the stack under the pointer
+    #[inline]
+    const fn stack_pointer(&self) -> Pointer {
+        (self.pointer.0 / 4, self.pointer.1 / 4)
+    }
+
+    /// Turn the machine into a [`Program`] with its instructions and
+    /// stacks, which are the initial stacks if it has not run
+    #[must_use]
+    pub fn into_program(self) -> Program<N, InstructionPlane, StackPlane>
+    where
+        InstructionPlane: Clone,
+        StackPlane: Clone,
+    {
+        Program::new(self.instructions, self.stacks)
+    }
+
+    /// Get the instruction plane
+    #[must_use]
+    pub const fn instructions(&self) -> &InstructionPlane {
+        &self.instructions
+    }
+
+    /// Get the stack plane, with the stacks as they are now
+    #[must_use]
+    pub const fn stacks(&self) -> &StackPlane {
+        &self.stacks
+    }
+
+    /// Get the stack under the pointer, if it is in the stack plane
+    #[must_use]
+    pub fn current_stack(&self) -> Option<&StackType> {
+        self.stacks.get(self.stack_pointer())
+    }
+
+    /// Get the instruction under the pointer without running it
+    #[must_use]
+    pub fn peek_instruction(&self) -> Option<Instruction> {
+        self.instructions.get(self.pointer).copied()
+    }
+
+    /// Get the stack under the pointer, following the
+    /// [`MissingStackPolicy`] if it is not in the stack plane
+    #[inline]
+    fn current_stack_mut(&mut self) -> Result<&mut StackType, StepError> {
+        self.stack_mut_at(self.pointer)
+    }
+
+    /// Get the stack under an instruction position, like
+    /// [`Machine::current_stack_mut`]
+    #[inline]
+    fn stack_mut_at(&mut self, pointer: Pointer) -> Result<&mut StackType, StepError> {
+        let stack_pointer = (pointer.0 / 4, pointer.1 / 4);
+
+        #[cfg(feature = "std")]
+        let position = self
+            .positions
+            .as_ref()
+            .and_then(|positions| positions.get(pointer).copied());
+        #[cfg(not(feature = "std"))]
+        let position = None;
+
+        let Self {
+            stacks,
+            null_stack,
+            missing_stack_policy,
+            ..
+        } = self;
+        let err = StepError::StackOutOfRange {
+            stack: stack_pointer,
+            position,
+        };
+        match (
+            stacks.get_mut(stack_pointer),
+            missing_stack_policy,
+            null_stack,
+        ) {
+            (Some(stack), _, _) => Ok(stack),
+            (None, MissingStackPolicy::Empty, Some(null_stack)) => {
+                // Discard anything pushed since it was last used
+                while null_stack.pop().is_some() {}
+                Ok(null_stack)
+            }
+            (None, MissingStackPolicy::Panic, _) => panic!("{err}"),
+            (None, _, _) => Err(err),
+        }
+    }
+
+    /// Move the pointer back to the start, facing right, and set the
+    /// machine running again. The registers and stacks are not changed
+    pub fn goto_start(&mut self) {
+        self.pointer = (0, 0);
+        self.velocity = 0b00;
+        self.set_state(State::Running);
+    }
+
+    /// Provide input to the machine when in the `InputWaiting` state.
+    /// If it is waiting for [`InputKind::All`], this is the same as
+    /// [`Machine::feed_all`] with just this input
     pub fn input(&mut self, input: N) {
-        if matches!(self.state, State::InputWaiting) {
-            self.register = input;
-            self.state = State::Running;
+        match self.pending_input_kind() {
+            Some(InputKind::All) => self.feed_all([input]),
+            Some(_) => {
+                self.register = input;
+                self.set_state(State::Running);
+            }
+            None => {}
+        }
+    }
+
+    /// Provide every input to the machine when it is waiting for
+    /// [`InputKind::All`], from an [`InputAll`](io::IO::InputAll)
+    /// instruction. The inputs are pushed in order onto the stack that
+    /// the instruction is over, so the first input is at the bottom and
+    /// the last input is on top
+    pub fn feed_all(&mut self, inputs: impl IntoIterator<Item = N>) {
+        if self.pending_input_kind() == Some(InputKind::All) {
+            self.push_inputs(inputs);
+            self.set_state(State::Running);
+        }
+    }
+
+    /// Push inputs onto the stack of the last
+    /// [`InputAll`](io::IO::InputAll) instruction
+    fn push_inputs(&mut self, inputs: impl IntoIterator<Item = N>) {
+        // The stack was checked when the instruction ran
+        if let Ok(stack) = self.stack_mut_at(self.input_pointer) {
+            stack.push_all(inputs);
         }
     }
 
@@ -113,11 +949,1868 @@ where
         self.state
     }
 
+    /// Call `callback` with the old and new states whenever the state
+    /// changes, replacing any previous callback.
+    /// The callback must be [`Send`], so that machines can be sent to
+    /// other threads
+    #[cfg(feature = "std")]
+    pub fn on_state_change(&mut self, callback: Box<dyn FnMut(State, State) + Send>) {
+        self.state_callback = Some(StateCallback::Boxed(callback));
+    }
+
+    /// Call `callback` with the old and new states whenever the state
+    /// changes, replacing any previous callback.
+    /// This does not need an allocation, so it also works without std
+    pub fn on_state_change_fn(&mut self, callback: fn(State, State)) {
+        self.state_callback = Some(StateCallback::Fn(callback));
+    }
+
+    /// Change the state, calling the state change callback if it is different
+    fn set_state(&mut self, state: State) {
+        let old = core::mem::replace(&mut self.state, state);
+        if old == state {
+            return;
+        }
+        match &mut self.state_callback {
+            #[cfg(feature = "std")]
+            Some(StateCallback::Boxed(callback)) => callback(old, state),
+            Some(StateCallback::Fn(callback)) => callback(old, state),
+            None => {}
+        }
+    }
+
+    /// Get the kind of input that the machine is waiting for, or
+    /// `None` if it is not in the `InputWaiting` state
+    #[must_use]
+    pub const fn pending_input_kind(&self) -> Option<InputKind> {
+        if matches!(self.state, State::InputWaiting) {
+            Some(self.input_kind)
+        } else {
+            None
+        }
+    }
+
     pub const fn get_pointer(&self) -> Pointer {
         self.pointer
     }
 
+    pub const fn get_velocity(&self) -> Velocity {
+        self.velocity
+    }
+
+    /// Set the velocity of the pointer. Only the lowest 2 bits are used
+    pub const fn set_velocity(&mut self, velocity: Velocity) {
+        self.velocity = velocity & 0b11;
+    }
+
+    /// Get the direction of the pointer, see [`Machine::get_velocity`]
+    #[must_use]
+    pub const fn get_direction(&self) -> Direction {
+        Direction::from_velocity_bits(self.velocity)
+    }
+
+    /// Set the direction of the pointer, see [`Machine::set_velocity`]
+    pub const fn set_direction(&mut self, direction: Direction) {
+        self.velocity = direction.to_velocity();
+    }
+
+    /// Get the arrow character for the direction of the pointer
+    #[must_use]
+    pub const fn velocity_arrow(&self) -> char {
+        velocity_to_arrow(self.velocity)
+    }
+
     pub const fn get_register(&self) -> N {
         self.register
     }
+
+    pub const fn get_flag(&self) -> N {
+        self.flag
+    }
+
+    /// Enable the register file of [`REGISTER_COUNT`] registers, used by
+    /// the [`SelectRegister`](operator::Operator::SelectRegister),
+    /// [`StoreRegister`](operator::Operator::StoreRegister) and
+    /// [`LoadRegister`](operator::Operator::LoadRegister) operators.
+    /// The registers start at 0, with register 0 selected
+    pub const fn enable_register_file(&mut self) {
+        self.register_file = Some([N::ZERO; REGISTER_COUNT]);
+        self.selected_register = 0;
+    }
+
+    /// Seed the random number generator used by the
+    /// [`Random`](operator::Operator::Random) operator, restarting its
+    /// sequence. The seed is 0 by default
+    pub const fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Xorshift64::new(seed);
+    }
+
+    /// Get the register file, or `None` if it is not enabled
+    #[must_use]
+    pub const fn get_register_file(&self) -> Option<&[N; REGISTER_COUNT]> {
+        self.register_file.as_ref()
+    }
+
+    /// Get the number of the selected register in the register file
+    #[must_use]
+    pub const fn get_selected_register(&self) -> usize {
+        self.selected_register
+    }
+
+    /// Get the number of instructions that have been run
+    pub const fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Limit the number of values that the machine prints, on any
+    /// channel. The machine is halted with [`HaltReason::OutputLimit`]
+    /// instead of printing any more
+    #[must_use]
+    pub const fn with_output_limit(mut self, output_limit: usize) -> Self {
+        self.output_limit = Some(output_limit);
+        self
+    }
+
+    /// Get the number of values that the machine has printed
+    #[must_use]
+    pub const fn get_outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Set the maximum number of instructions to run, after which the
+    /// machine is halted with [`HaltReason::StepLimit`]
+    pub const fn set_step_limit(&mut self, step_limit: Option<usize>) {
+        self.step_limit = step_limit;
+    }
+
+    /// Set whether a step over a space also moves over the spaces
+    /// after it, instead of taking a step for each one.
+    /// Skipped spaces still count towards the steps and step limit.
+    /// It has no effect while livelock detection is enabled
+    pub const fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+    }
+
+    pub const fn get_edge_behavior(&self) -> EdgeBehavior {
+        self.edge_behavior
+    }
+
+    /// Set what the machine does when the pointer moves off of an edge
+    pub const fn set_edge_behavior(&mut self, edge_behavior: EdgeBehavior) {
+        self.edge_behavior = edge_behavior;
+    }
+
+    /// Start counting the values that operators and comparators pop
+    /// from empty stacks, which are replaced by defaults.
+    /// If tracking is already enabled, the count is not reset
+    pub const fn enable_underflow_tracking(&mut self) {
+        if self.underflows.is_none() {
+            self.underflows = Some(0);
+        }
+    }
+
+    /// Get the number of values popped from empty stacks since
+    /// [`Machine::enable_underflow_tracking`], or `None` if it is not enabled
+    #[must_use]
+    pub const fn underflow_count(&self) -> Option<usize> {
+        self.underflows
+    }
+
+    pub const fn get_missing_stack_policy(&self) -> MissingStackPolicy {
+        self.missing_stack_policy
+    }
+
+    /// Set what the machine does when the pointer is over a stack that is
+    /// not in the stack plane
+    pub fn set_missing_stack_policy(&mut self, missing_stack_policy: MissingStackPolicy)
+    where
+        StackType: Default,
+    {
+        self.missing_stack_policy = missing_stack_policy;
+        self.null_stack =
+            matches!(missing_stack_policy, MissingStackPolicy::Empty).then(StackType::default);
+    }
+
+    pub const fn get_duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Set what the [`Duplicate`](operator::Operator::Duplicate) operator
+    /// does when the stack is empty
+    pub const fn set_duplicate_policy(&mut self, duplicate_policy: DuplicatePolicy) {
+        self.duplicate_policy = duplicate_policy;
+    }
+
+    /// Run the machine until it stops, waits for input or is halted,
+    /// returning every value output on the [`Primary`](io::Channel::Primary)
+    /// channel and the final state
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    #[cfg(feature = "std")]
+    pub fn run_collect(&mut self) -> (Vec<N>, State) {
+        let mut outputs = Vec::new();
+        while matches!(self.state, State::Running) {
+            if let Some(output) = self.step() {
+                if output.channel() == io::Channel::Primary {
+                    outputs.extend(output.value().copied());
+                }
+            }
+        }
+        (outputs, self.state)
+    }
+
+    /// Run up to `max_steps` steps while the machine is running,
+    /// recording each instruction that runs.
+    /// Use [`trace_to_json`] to write the records as JSON
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    #[cfg(feature = "std")]
+    pub fn trace(&mut self, max_steps: usize) -> Vec<TraceRecord<N>> {
+        let mut records = Vec::new();
+        for _ in 0..max_steps {
+            if !matches!(self.state, State::Running) {
+                break;
+            }
+
+            let info = self.step_detailed();
+            let (instruction, pointer) = (info.instruction, info.pointer);
+            if let Some(instruction) = instruction {
+                records.push(TraceRecord {
+                    pointer,
+                    instruction: instruction.into(),
+                    velocity: self.velocity,
+                    register: self.register,
+                });
+            }
+        }
+        records
+    }
+
+    /// Run the machine until it stops or is halted, calling `get_input`
+    /// whenever it needs input and `on_output` with every value output on
+    /// the [`Primary`](io::Channel::Primary) channel, returning the final
+    /// state. The machine never waits for input
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    pub fn run_with_input_fn<FI: FnMut() -> N, FO: FnMut(&N)>(
+        &mut self,
+        get_input: FI,
+        mut on_output: FO,
+    ) -> State {
+        let mut input = io::FnInput(get_input);
+        while matches!(self.state, State::Running) {
+            if let Some(output) = self.step_with_input(&mut input) {
+                if let (io::Channel::Primary, Some(value)) = (output.channel(), output.value()) {
+                    on_output(value);
+                }
+            }
+        }
+        self.state
+    }
+
+    /// Get the coordinates of the stacks that are not empty, row by row
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn nonempty_stacks(&self) -> Vec<Pointer> {
+        self.stacks
+            .iter()
+            .filter(|(_, stack)| !stack.is_empty())
+            .map(|(pointer, _)| pointer)
+            .collect()
+    }
+
+    /// Get the items of each stack that is not empty, bottom first,
+    /// for checking the stacks a program leaves
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn stack_snapshot(&self) -> HashMap<Pointer, Vec<N>> {
+        self.stacks
+            .iter()
+            .filter(|(_, stack)| !stack.is_empty())
+            .map(|(pointer, stack)| (pointer, stack.iter().copied().collect()))
+            .collect()
+    }
+
+    /// Run the machine until it has output `count` values on the
+    /// [`Primary`](io::Channel::Primary) channel, or it stops,
+    /// waits for input or is halted, returning the output values.
+    /// Set a step limit with [`Machine::set_step_limit`] to avoid running
+    /// forever if the machine never outputs `count` values
+    ///
+    /// # Panics
+    /// Panics if the pointer is over a stack that is not in the stack plane
+    #[cfg(feature = "std")]
+    pub fn run_until_outputs(&mut self, count: usize) -> Vec<N> {
+        let mut outputs = Vec::new();
+        while outputs.len() < count && matches!(self.state, State::Running) {
+            if let Some(output) = self.step() {
+                if output.channel() == io::Channel::Primary {
+                    outputs.extend(output.value().copied());
+                }
+            }
+        }
+        outputs
+    }
+
+    /// Get an iterator that runs the machine, yielding each value output
+    /// on the [`Primary`](io::Channel::Primary) channel as it is output.
+    /// The iterator ends when the machine stops, waits for input or is
+    /// halted, so a new stream can be taken once input is provided.
+    /// Unlike [`Machine::run_collect`], the outputs are not stored
+    ///
+    /// Iterating panics if the pointer is over a stack that is not in the
+    /// stack plane
+    pub const fn output_stream(
+        &mut self,
+    ) -> OutputStream<'_, N, InstructionPlane, StackType, StackPlane> {
+        OutputStream(self)
+    }
+
+    /// Count the instructions that are not spaces
+    #[must_use]
+    pub fn instruction_count(&self) -> usize {
+        plane::count_instructions(&self.instructions)
+    }
+
+    /// Find the instructions that cannot be reached from the current
+    /// pointer and velocity, returning their positions in row order.
+    ///
+    /// Comparators and conditional mirrors are followed in every
+    /// direction that they can turn the pointer. Jumps depend on the
+    /// stack, so they are not followed, and instructions that can only
+    /// be reached by jumping are reported as unreachable
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn validate_reachability(&self) -> Vec<Pointer> {
+        let reachable = self.reachable_cells();
+        self.instructions
+            .iter()
+            .filter(|&((x, y), instruction)| {
+                !matches!(instruction, Instruction::Space)
+                    && !reachable[y * self.instructions.width() + x]
+            })
+            .map(|(pointer, _)| pointer)
+            .collect()
+    }
+
+    /// Find the instructions that can be reached from the current
+    /// pointer and velocity, returning their positions in row order.
+    /// This is the opposite of [`Machine::validate_reachability`], so
+    /// spaces are not included
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn reachable_instructions(&self) -> Vec<Pointer> {
+        let reachable = self.reachable_cells();
+        self.instructions
+            .iter()
+            .filter(|&((x, y), instruction)| {
+                !matches!(instruction, Instruction::Space)
+                    && reachable[y * self.instructions.width() + x]
+            })
+            .map(|(pointer, _)| pointer)
+            .collect()
+    }
+
+    /// Find which cells can be reached, in row order. Each pointer and
+    /// velocity is only followed once, so this stops on programs that
+    /// loop, and the pending states are bounded by 4 times the number of
+    /// cells
+    #[cfg(feature = "std")]
+    fn reachable_cells(&self) -> Vec<bool> {
+        use crate::comparator::Comparator;
+        use core::cmp::Ordering;
+
+        let width = self.instructions.width();
+        let index =
+            |(x, y): Pointer, velocity: Velocity| ((y * width + x) << 2) | usize::from(velocity);
+        let mut visited = vec![false; width * self.instructions.height() * 4];
+
+        let mut pending = vec![(self.pointer, self.velocity)];
+        while let Some((pointer, velocity)) = pending.pop() {
+            let Some(&instruction) = self.instructions.get(pointer) else {
+                continue;
+            };
+            if core::mem::replace(&mut visited[index(pointer, velocity)], true) {
+                continue;
+            }
+
+            let mut follow =
+                |velocity| pending.push((self.next_pointer(pointer, velocity), velocity));
+            match instruction {
+                Instruction::Deflector(deflector) => {
+                    follow(deflector.apply_conditional(velocity, &N::ZERO));
+                    follow(deflector.apply_conditional(velocity, &N::ONE));
+                }
+                Instruction::Comparator(_) => {
+                    for ordering in [Ordering::Less, Ordering::Equal, Ordering::Greater] {
+                        follow(Comparator::turn(velocity, ordering));
+                    }
+                }
+                Instruction::Operator(operator::Operator::Jump) => {}
+                _ => follow(velocity),
+            }
+        }
+
+        visited
+            .chunks(4)
+            .map(|velocities| velocities.contains(&true))
+            .collect()
+    }
+
+    /// Check if the program has an [`Input`](io::IO::Input),
+    /// [`InputChar`](io::IO::InputChar) or [`InputAll`](io::IO::InputAll)
+    /// instruction.
+    /// This does not check if the instruction can be reached
+    #[must_use]
+    pub fn uses_input(&self) -> bool {
+        plane::contains_instruction(&self.instructions, |instruction| {
+            matches!(
+                instruction,
+                Instruction::IO(io::IO::Input | io::IO::InputChar | io::IO::InputAll)
+            )
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<N, InstructionPlane, StackType, StackPlane> Machine<N, InstructionPlane, StackType, StackPlane>
+where
+    N: Number + Display,
+    InstructionPlane: Plane<Item = Instruction>,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType>,
+{
+    /// Create a human-readable report of the pointer, velocity, register,
+    /// state, current stack (from bottom to top) and the instruction
+    /// under the pointer
+    #[must_use]
+    pub fn dump(&self) -> String {
+        let stack = self.current_stack().map_or_else(
+            || "out of range".to_owned(),
+            |stack| {
+                let items: Vec<String> = stack.iter().map(ToString::to_string).collect();
+                format!("[{}]", items.join(", "))
+            },
+        );
+        let instruction = self.peek_instruction().map_or_else(
+            || "none".to_owned(),
+            |instruction| format!("{:?}", char::from(instruction)),
+        );
+
+        format!(
+            "pointer: {:?}\nvelocity: {} ({})\nregister: {}\nstate: {:?}\nstack: {stack}\ninstruction: {instruction}",
+            self.pointer,
+            velocity_to_arrow(self.velocity),
+            velocity_name(self.velocity),
+            self.register,
+            self.state,
+        )
+    }
+}
+
+impl<N, InstructionPlane, StackType, StackPlane> Machine<N, InstructionPlane, StackType, StackPlane>
+where
+    N: Number + Hash,
+    InstructionPlane: Plane<Item = Instruction>,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType>,
+{
+    /// Enable or disable livelock detection.
+    /// When enabled, the machine is halted with [`HaltReason::Livelock`] if
+    /// the pointer, velocity, registers and current stack are the same as in
+    /// one of the last 64 steps. Only the current stack is compared, so this
+    /// is a heuristic and may halt programs that change other stacks
+    pub fn set_livelock_detection(&mut self, enabled: bool) {
+        self.state_hash = enabled.then_some(Self::state_hash as fn(&Self) -> u64);
+        self.history = History::new();
+    }
+
+    /// Hash the pointer, velocity, registers and current stack
+    fn state_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.pointer.hash(&mut hasher);
+        self.velocity.hash(&mut hasher);
+        self.register.hash(&mut hasher);
+        self.flag.hash(&mut hasher);
+        self.register_file.hash(&mut hasher);
+        self.selected_register.hash(&mut hasher);
+        if let Some(stack) = self.current_stack() {
+            hasher.write_usize(stack.len());
+            for item in stack.iter() {
+                item.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Hash the instructions and stacks, for deduplicating or caching
+    /// programs. The registers, pointer, velocity and state are not
+    /// included, but the stacks are hashed as they are now, so this is
+    /// only the hash of the seeded stacks until the machine has run.
+    /// The hash is stable, so it can be stored
+    #[must_use]
+    pub fn program_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write_usize(self.instructions.width());
+        hasher.write_usize(self.instructions.height());
+        for (_, &instruction) in self.instructions.iter() {
+            hasher.write_u32(char::from(instruction).into());
+        }
+
+        hasher.write_usize(self.stacks.width());
+        hasher.write_usize(self.stacks.height());
+        for (_, stack) in self.stacks.iter() {
+            hasher.write_usize(stack.len());
+            for item in stack.iter() {
+                item.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+}
+
+impl<N, InstructionPlane, StackType, StackPlane> Machine<N, InstructionPlane, StackType, StackPlane>
+where
+    N: Number,
+    InstructionPlane: Plane<Item = Instruction>,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType> + Clone,
+{
+    /// Take a snapshot of the state, registers, pointer, velocity and
+    /// stacks of the machine, which can be restored with [`Machine::restore`]
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot<N, StackPlane> {
+        Snapshot {
+            state: self.state,
+            stacks: self.stacks.clone(),
+            register: self.register,
+            flag: self.flag,
+            steps: self.steps,
+            outputs: self.outputs,
+            pointer: self.pointer,
+            velocity: self.velocity,
+            input_kind: self.input_kind,
+            input_pointer: self.input_pointer,
+            register_file: self.register_file,
+            selected_register: self.selected_register,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Restore a snapshot taken with [`Machine::snapshot`]
+    pub fn restore(&mut self, snapshot: Snapshot<N, StackPlane>) {
+        let Snapshot {
+            state,
+            stacks,
+            register,
+            flag,
+            pointer,
+            velocity,
+            steps,
+            outputs,
+            input_kind,
+            input_pointer,
+            register_file,
+            selected_register,
+            rng,
+        } = snapshot;
+
+        self.set_state(state);
+        self.stacks = stacks;
+        self.register = register;
+        self.flag = flag;
+        self.pointer = pointer;
+        self.velocity = velocity;
+        self.steps = steps;
+        self.outputs = outputs;
+        self.input_kind = input_kind;
+        self.input_pointer = input_pointer;
+        self.register_file = register_file;
+        self.selected_register = selected_register;
+        self.rng = rng;
+        // The recent states may not have happened after the snapshot
+        self.history = History::new();
+    }
+}
+
+/// A lazy iterator over the outputs of a machine, see
+/// [`Machine::output_stream`]
+pub struct OutputStream<'a, N, InstructionPlane, StackType, StackPlane>(
+    &'a mut Machine<N, InstructionPlane, StackType, StackPlane>,
+)
+where
+    N: Default,
+    InstructionPlane: Plane<Item = Instruction>,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType>;
+
+impl<N, InstructionPlane, StackType, StackPlane> Iterator
+    for OutputStream<'_, N, InstructionPlane, StackType, StackPlane>
+where
+    N: Number,
+    InstructionPlane: Plane<Item = Instruction>,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType>,
+{
+    type Item = N;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while matches!(self.0.state, State::Running) {
+            if let Some(output) = self.0.step() {
+                if let (io::Channel::Primary, Some(&value)) = (output.channel(), output.value()) {
+                    return Some(value);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<N, InstructionPlane, StackType, StackPlane> Machine<N, InstructionPlane, StackType, StackPlane>
+where
+    N: Number,
+    InstructionPlane: Plane<Item = Instruction> + Clone,
+    StackType: Stack<Item = N>,
+    StackPlane: Plane<Item = StackType> + Clone,
+{
+    /// Start running a program from the start, as a new machine would,
+    /// but keeping the settings of this machine, such as the step limit.
+    /// The source positions are kept, so they should be from the same
+    /// source as the program
+    pub fn reset(&mut self, program: &Program<N, InstructionPlane, StackPlane>) {
+        self.set_state(State::default());
+        self.instructions = program.instructions().clone();
+        self.stacks = program.stacks().clone();
+        self.register = N::ZERO;
+        self.flag = N::ZERO;
+        self.pointer = Pointer::default();
+        self.velocity = Velocity::default();
+        self.steps = 0;
+        self.outputs = 0;
+        self.input_kind = InputKind::default();
+        self.input_pointer = Pointer::default();
+        self.history = History::new();
+        if self.register_file.is_some() {
+            self.enable_register_file();
+        }
+        self.rng = Xorshift64::new(self.seed);
+        if self.underflows.is_some() {
+            self.underflows = Some(0);
+        }
+        #[cfg(feature = "std")]
+        self.executed.clear();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        instruction::Instruction,
+        io::{Channel, InputKind, LineInput, Output, SliceInput},
+        load::{self, Machine},
+        plane::VecPlane,
+        stack::{Stack, VecStack},
+        Direction, SourcePosition, Velocity,
+    };
+
+    use super::{
+        from_char_grid, trace_to_json, DuplicatePolicy, EdgeBehavior, HaltReason, State, StepError,
+        TraceRecord,
+    };
+
+    fn load(source: &str) -> Machine<i32> {
+        load::from_str(source, &|value| value.parse::<i32>()).unwrap()
+    }
+
+    #[test]
+    fn slice_input_runs_to_completion() {
+        let mut machine = load("i,i+p");
+        let mut input = SliceInput::new(&[2, 3]);
+
+        let mut outputs = Vec::new();
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.step_with_input(&mut input) {
+                outputs.extend(output.value().copied());
+            }
+        }
+
+        assert!(matches!(machine.get_state(), State::Stopped));
+        assert_eq!(outputs, [5]);
+    }
+
+    #[test]
+    fn fibonacci_example() {
+        // Guards the outputs of a fixed program against changes to the
+        // stepping hot path
+        let mut machine = load(include_str!("../examples/fibonacci.msc"));
+        let mut input = SliceInput::new(&[100]);
+
+        let mut outputs = Vec::new();
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.step_with_input(&mut input) {
+                outputs.extend(output.value().copied());
+            }
+        }
+
+        assert_eq!(outputs, [0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89]);
+        assert_eq!(machine.get_steps(), 367);
+    }
+
+    #[test]
+    fn slice_input_exhausted() {
+        let mut machine = load("i,i+p");
+        let mut input = SliceInput::new(&[2]);
+
+        while matches!(machine.get_state(), State::Running) {
+            machine.step_with_input(&mut input);
+        }
+
+        assert!(matches!(machine.get_state(), State::InputWaiting));
+        assert_eq!(machine.get_register(), 2);
+
+        // Input can still be provided manually
+        machine.input(3);
+        let mut outputs = Vec::new();
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.step() {
+                outputs.extend(output.value().copied());
+            }
+        }
+        assert_eq!(outputs, [5]);
+    }
+
+    fn run(machine: &mut Machine<i32>) -> Vec<i32> {
+        machine.run_collect().0
+    }
+
+    #[test]
+    fn step_detailed() {
+        let mut machine = load("s 0 0 5\n.p");
+
+        let info = machine.step_detailed();
+        assert_eq!(info.instruction.map(char::from), Some('.'));
+        assert_eq!(info.pointer, (0, 0));
+        assert!(info.output.is_none());
+
+        let info = machine.step_detailed();
+        assert_eq!(info.instruction.map(char::from), Some('p'));
+        assert_eq!(info.pointer, (1, 0));
+        assert_eq!(info.output, Some(Output::Number(&5)));
+
+        // Leaving the program does not run an instruction
+        let info = machine.step_detailed();
+        assert!(info.instruction.is_none());
+        assert_eq!(info.pointer, (2, 0));
+        assert!(info.output.is_none());
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn run_collect() {
+        let mut machine = load("s 0 0 2 3\n.p+p+p");
+
+        assert_eq!(machine.run_collect(), (vec![3, 5, 5], State::Stopped));
+    }
+
+    #[test]
+    fn run_collect_input_waiting() {
+        let mut machine = load("s 0 0 4\n.pi+p");
+
+        assert_eq!(machine.run_collect(), (vec![4], State::InputWaiting));
+        machine.input(1);
+        assert_eq!(machine.run_collect(), (vec![1], State::Stopped));
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut machine = load("s 0 0 1 2\n.+,,p");
+        let snapshot = machine.snapshot();
+
+        // Pop and add from the stack, then push twice
+        for _ in 0..4 {
+            machine.step();
+        }
+        assert_eq!(machine.get_register(), 3);
+        assert_ne!(machine.snapshot(), snapshot);
+
+        machine.restore(snapshot.clone());
+        assert_eq!(machine.snapshot(), snapshot);
+        assert_eq!(machine.get_register(), 0);
+        assert_eq!(machine.get_pointer(), (0, 0));
+        assert_eq!(run(&mut machine), [3]);
+    }
+
+    #[test]
+    fn line_input_runs_to_completion() {
+        let mut machine = load("i,i+,i+p");
+        let parse = |token: &str| token.parse::<i32>();
+        let mut input = LineInput::from_reader(&b"2 3 4\n"[..], &parse, false);
+
+        let mut outputs = Vec::new();
+        while machine.get_state() == State::Running {
+            if let Some(output) = machine.step_with_input(&mut input) {
+                outputs.extend(output.value().copied());
+            }
+        }
+
+        assert_eq!(outputs, [9]);
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn source_position() {
+        let machine = load("#!/usr/bin/env msc\ns 0 0 1\n# Comment\n\n  .p");
+
+        assert_eq!(
+            machine.source_position((2, 1)),
+            Some(SourcePosition { line: 4, column: 2 })
+        );
+    }
+
+    #[test]
+    fn stack_out_of_range_position() {
+        let mut machine = load("s 0 0 1\n# Comment\n>   ,");
+        // Replace the stacks with a 1 x 1 stack plane, so that the ','
+        // is over a missing stack
+        machine.restore(load(">").snapshot());
+
+        for _ in 0..4 {
+            assert!(matches!(machine.try_step(), Ok(None)));
+        }
+
+        let err = machine.try_step().unwrap_err();
+        assert!(matches!(
+            err,
+            StepError::StackOutOfRange {
+                stack: (1, 0),
+                position: Some(SourcePosition { line: 2, column: 4 })
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "stack pointer out of range at line 3, column 5: (1, 0)"
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let mut machine = load("s 0 0 1 2 3\n.v\n p");
+        machine.step();
+        machine.step();
+
+        assert_eq!(
+            machine.dump(),
+            "pointer: (1, 1)\nvelocity: v (Down)\nregister: 3\nstate: Running\nstack: [1, 2]\ninstruction: 'p'"
+        );
+    }
+
+    #[test]
+    fn dump_stopped() {
+        let mut machine = load(">");
+        machine.step();
+        machine.step();
+
+        let dump = machine.dump();
+        assert!(dump.contains("pointer: (1, 0)"));
+        assert!(dump.contains("state: Stopped"));
+        assert!(dump.contains("instruction: none"));
+    }
+
+    #[test]
+    fn print_char() {
+        let mut machine = load("s 0 0 -1 72\n.P.pP");
+
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), Some(Output::Char(&72)));
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), Some(Output::Number(&-1)));
+        // Invalid characters are still output, with their value
+        assert_eq!(machine.step(), Some(Output::Char(&-1)));
+    }
+
+    #[test]
+    fn print_err() {
+        let source = "s 0 0 2 1\n.e.p";
+
+        let (mut primary, mut error) = (Vec::new(), Vec::new());
+        let mut machine = load(source);
+        while matches!(machine.get_state(), State::Running) {
+            match machine
+                .step()
+                .and_then(|output| Some((output.channel(), *output.value()?)))
+            {
+                Some((Channel::Primary, value)) => primary.push(value),
+                Some((Channel::Error, value)) => error.push(value),
+                None => {}
+            }
+        }
+        assert_eq!(primary, [2]);
+        assert_eq!(error, [1]);
+
+        // Only the primary channel is collected
+        assert_eq!(load(source).run_collect(), (vec![2], State::Stopped));
+    }
+
+    fn instructions(source: &str) -> VecPlane<Instruction> {
+        source
+            .lines()
+            .map(|line| line.chars().map(|c| c.try_into().unwrap()).collect())
+            .collect::<Vec<Vec<_>>>()
+            .into()
+    }
+
+    #[test]
+    fn with_state() {
+        // Start on the print, moving down with a register of 3
+        let mut machine: Machine<i32> = Machine::with_state(
+            instructions(">  v\n.  p\n   p"),
+            vec![vec![VecStack::new()]].into(),
+            3,
+            (3, 1),
+            0b10,
+        );
+
+        assert_eq!(machine.step(), Some(Output::Number(&3)));
+        assert_eq!(machine.get_pointer(), (3, 2));
+        assert_eq!(machine.run_collect(), (vec![3], State::Stopped));
+    }
+
+    #[test]
+    fn with_state_out_of_range() {
+        let mut machine: Machine<i32> = Machine::with_state(
+            instructions(">p"),
+            vec![vec![VecStack::new()]].into(),
+            3,
+            (2, 0),
+            0b00,
+        );
+
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn edge_stop() {
+        let mut machine = load("<  p");
+
+        machine.step();
+        assert_eq!(machine.get_state(), State::Running);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn edge_wrap() {
+        let mut machine = load("<  p");
+        machine.set_edge_behavior(EdgeBehavior::Wrap);
+
+        machine.step();
+        assert_eq!(machine.get_pointer(), (3, 0));
+        assert_eq!(machine.step(), Some(Output::Number(&0)));
+        assert_eq!(machine.get_state(), State::Running);
+    }
+
+    #[test]
+    fn edge_wrap_vertical() {
+        let mut machine = load("^  p\n p");
+        machine.set_edge_behavior(EdgeBehavior::Wrap);
+
+        machine.step();
+        assert_eq!(machine.get_pointer(), (0, 1));
+    }
+
+    #[test]
+    fn duplicate_policy() {
+        let mut machine = load("d.p.p");
+        assert_eq!(machine.get_duplicate_policy(), DuplicatePolicy::PushZero);
+
+        machine.step();
+        assert!(machine.current_stack().unwrap().iter().eq(&[0]));
+        assert_eq!(run(&mut machine), [0, 0]);
+
+        let mut machine = load("d.p.p");
+        machine.set_duplicate_policy(DuplicatePolicy::NoOp);
+
+        machine.step();
+        assert!(machine.current_stack().unwrap().is_empty());
+        assert_eq!(run(&mut machine), [0, 0]);
+    }
+
+    #[test]
+    fn duplicate_policy_non_empty() {
+        let mut machine = load("s 0 0 5\nd.p.p.p");
+        machine.set_duplicate_policy(DuplicatePolicy::NoOp);
+
+        assert_eq!(run(&mut machine), [5, 5, 0]);
+    }
+
+    #[test]
+    fn on_state_change() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = load("ip");
+        let recorded = Arc::clone(&changes);
+        machine.on_state_change(Box::new(move |old, new| {
+            recorded.lock().unwrap().push((old, new));
+        }));
+
+        machine.step();
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [(State::Running, State::InputWaiting)]
+        );
+        machine.input(5);
+        assert_eq!(
+            changes.lock().unwrap()[1],
+            (State::InputWaiting, State::Running)
+        );
+        // Inputting again does not change the state
+        machine.input(6);
+        assert_eq!(changes.lock().unwrap().len(), 2);
+
+        machine.run_collect();
+        assert_eq!(
+            changes.lock().unwrap()[2..],
+            [(State::Running, State::Stopped)]
+        );
+    }
+
+    #[test]
+    fn flag() {
+        // Store 5, change the register to 8, then restore 5
+        let mut machine = load("s 0 0 3 5\n.f+pFp");
+
+        assert_eq!(machine.run_collect(), (vec![8, 5], State::Stopped));
+        assert_eq!(machine.get_flag(), 5);
+    }
+
+    #[test]
+    fn push_pointer() {
+        // Push x at (3, 1) and y at (3, 2)
+        let mut machine = load(">  v\n   x\n   y");
+        machine.run_collect();
+
+        assert!(machine.current_stack().unwrap().iter().eq(&[3, 2]));
+    }
+
+    #[test]
+    fn velocity_arrow() {
+        let mut machine = load(">");
+
+        for (velocity, arrow) in [(0b00, '>'), (0b01, '<'), (0b10, 'v'), (0b11, '^')] {
+            machine.set_velocity(velocity);
+            assert_eq!(machine.get_velocity(), velocity);
+            assert_eq!(machine.velocity_arrow(), arrow);
+        }
+
+        // Only the lowest 2 bits are used
+        machine.set_velocity(0b110);
+        assert_eq!(machine.velocity_arrow(), 'v');
+    }
+
+    #[test]
+    fn direction() {
+        let mut machine = load(">");
+        assert_eq!(machine.get_direction(), Direction::Right);
+
+        for direction in Direction::ALL {
+            machine.set_direction(direction);
+            assert_eq!(machine.get_direction(), direction);
+            assert_eq!(machine.get_velocity(), Velocity::from(direction));
+        }
+
+        machine.set_velocity(0b110);
+        assert_eq!(machine.get_direction(), Direction::Down);
+    }
+
+    #[test]
+    fn flag_snapshot() {
+        let mut machine = load("s 0 0 5\n.f");
+        let snapshot = machine.snapshot();
+        machine.run_collect();
+        assert_eq!(machine.get_flag(), 5);
+
+        machine.restore(snapshot);
+        assert_eq!(machine.get_flag(), 0);
+    }
+
+    #[test]
+    fn register_file() {
+        let mut machine = load("s 0 0 5 7\n.0[.1[0]p1]p");
+        machine.enable_register_file();
+
+        assert_eq!(machine.run_collect(), (vec![7, 5], State::Stopped));
+        assert_eq!(machine.get_register_file(), Some(&[7, 5, 0, 0]));
+        assert_eq!(machine.get_selected_register(), 1);
+    }
+
+    #[test]
+    fn register_file_disabled() {
+        let mut machine = load("s 0 0 5 7\n.0[.1[0]p1]p");
+
+        // The register instructions do nothing
+        assert_eq!(machine.run_collect(), (vec![5, 5], State::Stopped));
+        assert_eq!(machine.get_register_file(), None);
+        assert_eq!(machine.get_selected_register(), 0);
+    }
+
+    #[test]
+    fn register_file_snapshot() {
+        let mut machine = load("s 0 0 5\n.2[");
+        machine.enable_register_file();
+        let snapshot = machine.snapshot();
+        machine.run_collect();
+        assert_eq!(machine.get_register_file(), Some(&[0, 0, 5, 0]));
+
+        machine.restore(snapshot);
+        assert_eq!(machine.get_register_file(), Some(&[0; 4]));
+        assert_eq!(machine.get_selected_register(), 0);
+    }
+
+    #[test]
+    fn random_reproducible() {
+        let outputs = |seed| {
+            let mut machine = load(">%.pv\n^   <");
+            machine.set_seed(seed);
+            machine.run_until_outputs(20)
+        };
+
+        assert_eq!(outputs(5), outputs(5));
+        assert_ne!(outputs(5), outputs(6));
+        // Without a bound, the numbers are from the full range
+        assert!(outputs(5).iter().any(|&value| value < 0));
+    }
+
+    #[test]
+    fn random_bounded() {
+        let source = format!("s 0 0{}\n>%.pv\n^   <", " 6".repeat(100));
+        let mut machine = load(&source);
+        machine.set_seed(3);
+        let outputs = machine.run_until_outputs(100);
+
+        assert!(outputs.iter().all(|value| (0..6).contains(value)));
+        // Resetting restarts the sequence from the seed
+        machine.reset(&load(&source).into_program());
+        assert_eq!(machine.run_until_outputs(100), outputs);
+    }
+
+    #[test]
+    fn stack_snapshot() {
+        let mut machine = load("s 0 0 1 2\ns 1 0 9\n.,,    ,\n\n\n\n ");
+        machine.run_collect();
+
+        let expected = HashMap::from([((0, 0), vec![1, 2, 2]), ((1, 0), vec![9, 2])]);
+        // The empty stacks in the second row are not included
+        assert_eq!(machine.stack_snapshot(), expected);
+    }
+
+    #[test]
+    fn run_until_outputs() {
+        // Count up forever
+        let mut machine = load("s 0 0 1\n>d+v\n^ p<");
+
+        assert_eq!(machine.run_until_outputs(5), [1, 2, 3, 4, 5]);
+        assert_eq!(machine.get_state(), State::Running);
+        assert_eq!(machine.run_until_outputs(2), [6, 7]);
+    }
+
+    #[test]
+    fn run_until_outputs_stopped() {
+        let mut machine = load("s 0 0 1 2\n.p.p");
+
+        assert_eq!(machine.run_until_outputs(5), [2, 1]);
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn step_limit() {
+        // Bounce forever without outputting
+        let mut machine = load("><");
+        machine.set_step_limit(Some(10));
+
+        assert!(machine.run_until_outputs(1).is_empty());
+        assert_eq!(
+            machine.get_state(),
+            State::Halted {
+                reason: HaltReason::StepLimit
+            }
+        );
+        assert_eq!(machine.get_steps(), 10);
+    }
+
+    #[test]
+    fn trace() {
+        let mut machine = load("s 0 0 2 3\n.+\\\n  p");
+        let records = machine.trace(100);
+
+        let record = |pointer, instruction, velocity, register| TraceRecord {
+            pointer,
+            instruction,
+            velocity,
+            register,
+        };
+        assert_eq!(
+            records,
+            [
+                record((0, 0), '.', 0, 3),
+                record((1, 0), '+', 0, 5),
+                record((2, 0), '\\', 2, 5),
+                record((2, 1), 'p', 2, 5),
+            ]
+        );
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn trace_max_steps() {
+        let mut machine = load("><");
+        assert_eq!(machine.trace(3).len(), 3);
+        assert_eq!(machine.get_steps(), 3);
+    }
+
+    #[test]
+    fn trace_json() {
+        let mut machine = load("s 0 0 2 3\n.+\\\n  p");
+        let records = machine.trace(100);
+        let json = trace_to_json(&records);
+
+        // Parse the records back out of the JSON by hand
+        let body = json.strip_prefix("[{").unwrap().strip_suffix("}]").unwrap();
+        let parsed: Vec<TraceRecord<i32>> = body
+            .split("},{")
+            .map(|object| {
+                let fields: HashMap<&str, &str> = object
+                    .split(",\"")
+                    .map(|field| {
+                        let (key, value) = field.split_once("\":").unwrap();
+                        (key.trim_start_matches('"'), value)
+                    })
+                    .collect();
+                assert_eq!(fields.len(), 4);
+
+                let (x, y) = fields["pointer"]
+                    .strip_prefix('[')
+                    .and_then(|pointer| pointer.strip_suffix(']'))
+                    .and_then(|pointer| pointer.split_once(','))
+                    .unwrap();
+                let instruction = fields["instruction"]
+                    .strip_prefix('"')
+                    .and_then(|instruction| instruction.strip_suffix('"'))
+                    .unwrap();
+                let instruction = match instruction {
+                    "\\\\" => '\\',
+                    instruction => instruction.chars().next().unwrap(),
+                };
+                TraceRecord {
+                    pointer: (x.parse().unwrap(), y.parse().unwrap()),
+                    instruction,
+                    velocity: fields["velocity"].parse().unwrap(),
+                    register: fields["register"].parse().unwrap(),
+                }
+            })
+            .collect();
+
+        assert_eq!(parsed, records);
+        assert!(
+            json.starts_with(r#"[{"pointer":[0,0],"instruction":".","velocity":0,"register":3},"#)
+        );
+        assert!(json.contains(r#""instruction":"\\""#));
+        assert_eq!(trace_to_json::<i32>(&[]), "[]");
+    }
+
+    #[test]
+    fn output_limit() {
+        // Print on every loop forever
+        let mut machine = load(">pv\n^ <").with_output_limit(3);
+
+        assert_eq!(
+            machine.run_collect(),
+            (
+                vec![0, 0, 0],
+                State::Halted {
+                    reason: HaltReason::OutputLimit
+                }
+            )
+        );
+        assert_eq!(machine.get_outputs(), 3);
+        // The machine halts on the fourth print, not after the third
+        assert_eq!(machine.get_pointer(), (1, 0));
+    }
+
+    #[test]
+    fn output_limit_not_reached() {
+        let mut machine = load("s 0 0 5\n.pep").with_output_limit(3);
+
+        assert_eq!(machine.run_collect(), (vec![5, 5], State::Stopped));
+        assert_eq!(machine.get_outputs(), 3);
+    }
+
+    #[test]
+    fn fast_forward() {
+        let source = "+      v\n\n\n\n       \n\n       +\n       p\n  p    <";
+        let mut slow = load(source);
+        let mut fast = load(source);
+        fast.set_fast_forward(true);
+
+        // The first step runs the '+', the second skips to the 'v'
+        fast.step();
+        fast.step();
+        assert_eq!(fast.get_pointer(), (7, 0));
+        assert_eq!(fast.get_steps(), 7);
+
+        assert_eq!(run(&mut fast), run(&mut slow));
+        assert_eq!(fast.get_steps(), slow.get_steps());
+        assert_eq!(fast.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn fast_forward_step_limit() {
+        let mut machine = load("        p");
+        machine.set_fast_forward(true);
+        machine.set_step_limit(Some(5));
+
+        machine.step();
+        assert_eq!(machine.get_pointer(), (5, 0));
+        assert_eq!(machine.get_steps(), 5);
+        assert!(run(&mut machine).is_empty());
+    }
+
+    #[test]
+    fn fast_forward_wrap() {
+        // A blank line with wrapping edges takes a lap per step
+        let mut machine = load("    ");
+        machine.set_fast_forward(true);
+        machine.set_edge_behavior(EdgeBehavior::Wrap);
+
+        machine.step();
+        assert_eq!(machine.get_pointer(), (1, 0));
+        assert_eq!(machine.get_steps(), 5);
+        assert_eq!(machine.get_state(), State::Running);
+    }
+
+    #[test]
+    fn livelock() {
+        let mut machine = load("><");
+        machine.set_livelock_detection(true);
+
+        assert!(machine.run_collect().0.is_empty());
+        assert_eq!(
+            machine.get_state(),
+            State::Halted {
+                reason: HaltReason::Livelock
+            }
+        );
+        // The fourth state, on the '<' moving right, repeats the second
+        assert_eq!(machine.get_steps(), 3);
+    }
+
+    #[test]
+    fn livelock_changing_register() {
+        // Count up forever, which never repeats a state
+        let mut machine = load("s 0 0 1\n>d+v\n^ p<");
+        machine.set_livelock_detection(true);
+
+        assert_eq!(machine.run_until_outputs(100).len(), 100);
+        assert_eq!(machine.get_state(), State::Running);
+    }
+
+    #[test]
+    fn livelock_disabled() {
+        let mut machine = load("><");
+        machine.set_step_limit(Some(100));
+
+        machine.run_collect();
+        assert_eq!(
+            machine.get_state(),
+            State::Halted {
+                reason: HaltReason::StepLimit
+            }
+        );
+    }
+
+    #[test]
+    fn nonempty_stacks() {
+        // 2 x 2 stacks, with only two seeded
+        let machine = load("s 1 0 1\ns 0 1 2 3\ns 1 1\n>\n\n\n\n     ");
+
+        assert_eq!(machine.nonempty_stacks(), [(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn nonempty_stacks_emptied() {
+        let mut machine = load("s 1 0 1\n    .");
+        assert_eq!(machine.nonempty_stacks(), [(1, 0)]);
+
+        // Pop the only item
+        machine.run_collect();
+        assert!(machine.nonempty_stacks().is_empty());
+    }
+
+    #[test]
+    fn goto_start() {
+        // Push the register, add 1 and print
+        let mut machine = load("s 0 0 1\nd+,pv\n    <");
+        assert_eq!(machine.run_collect(), (vec![1], State::Stopped));
+
+        machine.goto_start();
+        assert_eq!(machine.get_state(), State::Running);
+        assert_eq!(machine.get_pointer(), (0, 0));
+        assert_eq!(machine.get_register(), 1);
+        // The stack keeps the pushed register
+        assert!(machine.current_stack().unwrap().iter().eq(&[1, 1]));
+
+        assert_eq!(machine.run_collect(), (vec![2], State::Stopped));
+    }
+
+    #[test]
+    fn instruction_count() {
+        let machine = load("s 0 0 5\n>  .v # Comment\n\n   p<");
+
+        assert_eq!(machine.instruction_count(), 5);
+    }
+
+    #[test]
+    fn char_grid() {
+        let mut machine = from_char_grid::<i32>(&["i,v", "  +", "  p"]).unwrap();
+
+        assert_eq!(
+            machine.source_position((2, 1)),
+            Some(SourcePosition { line: 1, column: 2 })
+        );
+        assert_eq!(machine.run_collect(), (vec![], State::InputWaiting));
+        machine.input(21);
+        assert_eq!(machine.run_collect(), (vec![42], State::Stopped));
+
+        assert!(matches!(
+            from_char_grid::<i32>(&["s 0 0 1"]),
+            Err(load::Error::InvalidInstruction(_))
+        ));
+    }
+
+    #[test]
+    fn uses_input() {
+        assert!(load("i,i+p").uses_input());
+        assert!(load(">  v\n   i").uses_input());
+        assert!(load("IP").uses_input());
+        assert!(load("@.p").uses_input());
+        assert!(!load("s 0 0 1\n.p").uses_input());
+    }
+
+    #[test]
+    fn program_hash() {
+        let source = "s 0 0 1 2\n>  p\n.";
+        let mut machine = load(source);
+        let hash = machine.program_hash();
+
+        assert_eq!(load(source).program_hash(), hash);
+        // Running without changing the stacks does not change the hash
+        assert_eq!(run(&mut machine), [0]);
+        assert_eq!(machine.program_hash(), hash);
+
+        assert_ne!(load("s 0 0 1 3\n>  p\n.").program_hash(), hash);
+        assert_ne!(load("s 0 0 1 2\n>  P\n.").program_hash(), hash);
+        assert_ne!(load("s 0 0 1 2\n>  p \n.").program_hash(), hash);
+    }
+
+    #[test]
+    fn validate_reachability() {
+        // The bottom row is never reached, and nor is the end of the
+        // top row after the pointer turns down
+        let machine = load(">  v.p\n   z\n   p\n .p");
+
+        assert_eq!(
+            machine.validate_reachability(),
+            [(4, 0), (5, 0), (1, 3), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn validate_reachability_branches() {
+        // The zero comparator can turn either way, and the conditional
+        // mirror can reverse
+        let machine = load(">  v\n p z p\n   O\n   p\n p");
+        assert_eq!(machine.validate_reachability(), [(1, 4)]);
+
+        // Jumps are not followed
+        let machine = load("s 0 0 3 0\njp p");
+        assert_eq!(machine.validate_reachability(), [(1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn reachability_loop() {
+        // The arrows loop forever, but each cell is only reported once
+        let machine = load(">  v\n^  <\np");
+        assert_eq!(
+            machine.reachable_instructions(),
+            [(0, 0), (3, 0), (0, 1), (3, 1)]
+        );
+        assert_eq!(machine.validate_reachability(), [(0, 2)]);
+
+        // Wrapping around the edge also loops
+        let mut machine = load("s 0 0 1\n.p o");
+        machine.set_edge_behavior(EdgeBehavior::Wrap);
+        assert_eq!(machine.reachable_instructions(), [(0, 0), (1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn flush() {
+        let mut machine = load("s 0 0 1\n.pw");
+
+        machine.step();
+        assert_eq!(machine.step(), Some(Output::Number(&1)));
+        assert_eq!(machine.step(), Some(Output::Flush));
+        assert_eq!(machine.run_collect(), (vec![], State::Stopped));
+    }
+
+    #[test]
+    fn buffered_until_flush() {
+        use crate::io::BufferedOutput;
+
+        let mut machine = load("s 0 0 1 2\n.p.pw");
+        let mut output = BufferedOutput::new(Vec::new(), Vec::new());
+
+        while machine.get_state() == State::Running {
+            if let Some(value) = machine.step() {
+                if value == Output::Flush {
+                    assert!(output.primary().is_empty());
+                }
+                output.emit(value).unwrap();
+            }
+        }
+        assert_eq!(output.primary(), b"2\n1\n");
+    }
+
+    #[test]
+    fn output_stream() {
+        // Counts up forever
+        let mut machine = load("s 0 0 1\n>d+pv\n^   <");
+
+        assert!(machine.output_stream().take(3).eq([1, 2, 3]));
+        assert_eq!(machine.get_state(), State::Running);
+        assert_eq!(machine.get_register(), 3);
+        assert_eq!(machine.get_pointer(), (4, 0));
+
+        // The stream continues from where it was left
+        assert!(machine.output_stream().take(2).eq([4, 5]));
+    }
+
+    #[test]
+    fn output_stream_input_waiting() {
+        let mut machine = load("s 0 0 4\n.pi+pe");
+
+        assert!(machine.output_stream().eq([4]));
+        assert_eq!(machine.get_state(), State::InputWaiting);
+
+        machine.input(1);
+        // Errors are not included
+        assert!(machine.output_stream().eq([1]));
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut machine = load("iz p\n p");
+        assert!(machine.executed_cells().is_empty());
+
+        machine.enable_coverage();
+        while machine.get_state() == State::Running {
+            machine.step_with_input(&mut SliceInput::new(&[0]));
+        }
+
+        // Zero passes straight through the comparator, missing the other branch
+        let expected: HashSet<_> = [(0, 0), (1, 0), (2, 0), (3, 0)].into();
+        assert_eq!(machine.executed_cells(), &expected);
+
+        machine.reset(&load("iz p\n p").into_program());
+        assert!(machine.executed_cells().is_empty());
+    }
+
+    #[test]
+    fn underflow_count() {
+        let mut machine = load("+.tc");
+        assert_eq!(machine.underflow_count(), None);
+
+        machine.enable_underflow_tracking();
+        run(&mut machine);
+        // Tuck pushes three values back, so the comparator does not underflow
+        assert_eq!(machine.underflow_count(), Some(4));
+    }
+
+    #[test]
+    fn underflow_count_non_empty() {
+        let mut machine = load("s 0 0 1\n+.+!z");
+        machine.enable_underflow_tracking();
+
+        machine.step();
+        assert_eq!(machine.underflow_count(), Some(0));
+        run(&mut machine);
+        assert_eq!(machine.underflow_count(), Some(2));
+
+        // Enabling again does not reset the count
+        machine.enable_underflow_tracking();
+        assert_eq!(machine.underflow_count(), Some(2));
+    }
+
+    #[test]
+    fn run_with_input_fn() {
+        // Add pairs of inputs until the sum is not zero
+        let mut machine = load(">i,iv\n^zp+<");
+        let mut count = 0;
+        let mut outputs = Vec::new();
+
+        let state = machine.run_with_input_fn(
+            || {
+                count += 1;
+                // 1, -1, 2, -2, 3, -3, then 4, 1
+                match count {
+                    8 => 1,
+                    _ if count % 2 == 1 => (count + 1) / 2,
+                    _ => -count / 2,
+                }
+            },
+            |&value| outputs.push(value),
+        );
+
+        assert_eq!(state, State::Stopped);
+        assert_eq!(outputs, [0, 0, 0, 5]);
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn run_with_input_fn_input_all() {
+        // The function never runs out, so only one input is read
+        let mut machine = load("@.p.p");
+        let mut count = 0;
+        let mut outputs = Vec::new();
+
+        let state = machine.run_with_input_fn(
+            || {
+                count += 1;
+                count
+            },
+            |&value| outputs.push(value),
+        );
+
+        assert_eq!(state, State::Stopped);
+        assert_eq!(outputs, [1, 0]);
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn pending_input_kind() {
+        let mut machine = load("i,IP");
+        assert_eq!(machine.pending_input_kind(), None);
+
+        machine.step();
+        assert_eq!(machine.pending_input_kind(), Some(InputKind::Number));
+        machine.input(1);
+        assert_eq!(machine.pending_input_kind(), None);
+
+        machine.step();
+        machine.step();
+        assert_eq!(machine.pending_input_kind(), Some(InputKind::Char));
+        machine.input(i32::from(b'A'));
+        assert_eq!(machine.run_collect(), (vec![65], State::Stopped));
+    }
+
+    #[test]
+    fn feed_all() {
+        let mut machine = load("@.p.p");
+
+        assert_eq!(machine.run_collect(), (vec![], State::InputWaiting));
+        assert_eq!(machine.pending_input_kind(), Some(InputKind::All));
+        machine.feed_all([1, 2, 3]);
+        assert_eq!(machine.get_state(), State::Running);
+        // The last input is on top
+        assert_eq!(machine.run_collect(), (vec![3, 2], State::Stopped));
+
+        // A single input is pushed like the others
+        let mut machine = load("@.p.p");
+        machine.step();
+        machine.input(5);
+        assert_eq!(machine.run_collect(), (vec![5, 0], State::Stopped));
+    }
+
+    #[test]
+    fn input_all_from_source() {
+        let mut machine = load("@.p.p");
+        let mut input = SliceInput::new(&[1, 2, 3]);
+        let mut outputs = Vec::new();
+        while machine.get_state() == State::Running {
+            if let Some(output) = machine.step_with_input(&mut input) {
+                outputs.extend(output.value().copied());
+            }
+        }
+
+        assert_eq!(outputs, [3, 2]);
+        assert_eq!(machine.get_state(), State::Stopped);
+
+        // With nothing to read, the machine waits
+        let mut machine = load("@.p");
+        machine.step_with_input(&mut SliceInput::new(&[]));
+        assert_eq!(machine.pending_input_kind(), Some(InputKind::All));
+        machine.step_with_input(&mut SliceInput::new(&[4, 5]));
+        assert_eq!(machine.run_collect(), (vec![5], State::Stopped));
+    }
+
+    #[test]
+    fn line_input_chars() {
+        let mut machine = load("IPiPIP");
+        let parse = |token: &str| token.parse::<i32>();
+        let mut input = LineInput::from_reader(&b"a\n66 bc\n"[..], &parse, false);
+
+        let mut outputs = Vec::new();
+        while machine.get_state() == State::Running {
+            if let Some(output) = machine.step_with_input(&mut input) {
+                outputs.push(output.to_string());
+            }
+        }
+
+        assert_eq!(outputs, ["a", "B", "b"]);
+        assert_eq!(machine.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn jump() {
+        // Jump over the first print to (3, 0)
+        let mut machine = load("s 0 0 7 3 0\njp .p");
+
+        assert_eq!(run(&mut machine), [7]);
+        assert!(matches!(machine.get_state(), State::Stopped));
+    }
+
+    #[test]
+    fn jump_out_of_range() {
+        let mut machine = load("s 0 0 9 0\njp");
+
+        machine.step();
+        assert!(matches!(machine.get_state(), State::Stopped));
+        assert_eq!(machine.get_pointer(), (0, 0));
+    }
+
+    #[test]
+    fn jump_negative() {
+        let mut machine = load("s 0 0 -1 0\njp");
+
+        assert!(run(&mut machine).is_empty());
+        assert_eq!(machine.get_pointer(), (0, 0));
+    }
+}
+
+#[cfg(test)]
+mod no_std_test {
+    use core::{
+        num::{ParseIntError, TryFromIntError},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::build::{self, Machine};
+
+    use super::{MissingStackPolicy, State, StepError};
+
+    fn build<const WIDTH: usize, const STACK_WIDTH: usize>(
+        source: &str,
+    ) -> Machine<i32, WIDTH, 1, 2, STACK_WIDTH, 1> {
+        build::from_str::<_, WIDTH, 1, 2, STACK_WIDTH, 1, ParseIntError, TryFromIntError>(
+            source,
+            &|value| value.parse(),
+            &|value: i32| value.try_into(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn missing_stack() {
+        // The ',' is over stack (1, 0), which is not in the 1 x 1 stack plane
+        let mut machine = build::<8, 1>(">   ,");
+
+        for _ in 0..4 {
+            assert!(matches!(machine.try_step(), Ok(None)));
+        }
+
+        assert!(matches!(
+            machine.try_step(),
+            Err(StepError::StackOutOfRange {
+                stack: (1, 0),
+                position: None
+            })
+        ));
+        // The machine is left as it was
+        assert!(matches!(machine.get_state(), State::Running));
+        assert_eq!(machine.get_pointer(), (4, 0));
+        assert!(matches!(
+            machine.try_step(),
+            Err(StepError::StackOutOfRange {
+                stack: (1, 0),
+                position: None
+            })
+        ));
+    }
+
+    #[test]
+    fn on_state_change_fn() {
+        static CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+        let mut machine = build::<4, 1>("i");
+        machine.on_state_change_fn(|old, new| {
+            assert_eq!((old, new), (State::Running, State::InputWaiting));
+            CHANGES.fetch_add(1, Ordering::Relaxed);
+        });
+
+        machine.step();
+        assert_eq!(machine.get_state(), State::InputWaiting);
+        assert_eq!(CHANGES.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn missing_stack_empty() {
+        // The stack (1, 0) is missing, so the pushes are discarded and
+        // the pops give zero
+        let mut machine = build::<8, 1>("s 0 0 3\n.   ,.p");
+        machine.set_missing_stack_policy(MissingStackPolicy::Empty);
+        machine.enable_underflow_tracking();
+
+        let mut outputs = [None; 2];
+        let mut i = 0;
+        while matches!(machine.get_state(), State::Running) {
+            if let Some(output) = machine.try_step().unwrap() {
+                outputs[i] = output.value().copied();
+                i += 1;
+            }
+        }
+        assert_eq!(outputs, [Some(0), None]);
+        assert_eq!(machine.underflow_count(), Some(1));
+    }
+
+    #[test]
+    #[should_panic = "stack pointer out of range: (1, 0)"]
+    fn missing_stack_panic() {
+        let mut machine = build::<8, 1>(">   ,");
+        machine.set_missing_stack_policy(MissingStackPolicy::Panic);
+        assert_eq!(
+            machine.get_missing_stack_policy(),
+            MissingStackPolicy::Panic
+        );
+
+        for _ in 0..5 {
+            let _ = machine.try_step();
+        }
+    }
+
+    #[test]
+    fn present_stack() {
+        let mut machine = build::<8, 2>(">   ,");
+
+        while matches!(machine.get_state(), State::Running) {
+            assert!(machine.try_step().is_ok());
+        }
+    }
 }
@@ -1,7 +1,10 @@
-use core::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Sub};
+use core::{
+    cmp::Ordering,
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Sub},
+};
 
 /// Generic number trait to group other traits and provide
-/// zero and one constants
+/// zero, one and bound constants
 pub trait Number
 where
     Self: Add<Output = Self>
@@ -19,6 +22,66 @@ where
 {
     const ZERO: Self;
     const ONE: Self;
+    /// The smallest value
+    const MIN: Self;
+    /// The largest value
+    const MAX: Self;
+
+    /// Convert to a `usize`, returning `None` if it does not fit
+    fn to_usize(self) -> Option<usize>;
+
+    /// Convert from a `usize`, returning `None` if it does not fit
+    fn from_usize(value: usize) -> Option<Self>;
+
+    /// Convert from a `usize`, saturating at [`Number::MAX`] if it does
+    /// not fit
+    #[must_use]
+    fn from_usize_saturating(value: usize) -> Self {
+        Self::from_usize(value).unwrap_or(Self::MAX)
+    }
+
+    /// Convert to a `usize`, clamping negative numbers to 0 and
+    /// numbers that are too large to `usize::MAX`
+    #[must_use]
+    fn to_usize_clamped(self) -> usize {
+        self.to_usize()
+            .unwrap_or(if self < Self::ZERO { 0 } else { usize::MAX })
+    }
+
+    /// Convert from the low bits of a `u128`, wrapping if it does not fit.
+    /// This defaults to the low bits that fit in a `usize`, reduced to
+    /// fit from 0 to [`Number::MAX`], so it never gives a negative number
+    #[must_use]
+    fn from_u128_wrapping(value: u128) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let low = value as usize;
+        match Self::MAX.to_usize() {
+            Some(max) if max < usize::MAX => Self::from_usize(low % (max + 1)),
+            _ => Self::from_usize(low),
+        }
+        .unwrap_or(Self::ZERO)
+    }
+
+    /// Get the absolute value. Unsigned numbers are their own absolute
+    /// value. The absolute value of a signed [`Number::MIN`] does not
+    /// fit, so it wraps to [`Number::MIN`].
+    /// This defaults to subtracting negative numbers from [`Number::ZERO`],
+    /// so it overflows on [`Number::MIN`] if subtraction does
+    #[must_use]
+    fn abs(self) -> Self {
+        if self < Self::ZERO {
+            Self::ZERO - self
+        } else {
+            self
+        }
+    }
+
+    /// Compare with another number for the comparators.
+    /// This defaults to [`Ord::cmp`]
+    #[must_use]
+    fn compare(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
 }
 
 macro_rules! number_impl {
@@ -26,6 +89,29 @@ macro_rules! number_impl {
         impl Number for $t {
             const ZERO: Self = 0;
             const ONE: Self = 1;
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn to_usize(self) -> Option<usize> {
+                usize::try_from(self).ok()
+            }
+
+            fn from_usize(value: usize) -> Option<Self> {
+                Self::try_from(value).ok()
+            }
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            fn from_u128_wrapping(value: u128) -> Self {
+                value as Self
+            }
+
+            fn abs(self) -> Self {
+                if self < Self::ZERO {
+                    self.wrapping_neg()
+                } else {
+                    self
+                }
+            }
         }
     };
     ( $t:ty, wrap ) => {
@@ -33,6 +119,24 @@ macro_rules! number_impl {
         impl Number for core::num::Wrapping<$t> {
             const ZERO: Self = Self(0);
             const ONE: Self = Self(1);
+            const MIN: Self = Self(<$t>::MIN);
+            const MAX: Self = Self(<$t>::MAX);
+
+            fn to_usize(self) -> Option<usize> {
+                self.0.to_usize()
+            }
+
+            fn from_usize(value: usize) -> Option<Self> {
+                <$t>::from_usize(value).map(Self)
+            }
+
+            fn from_u128_wrapping(value: u128) -> Self {
+                Self(<$t>::from_u128_wrapping(value))
+            }
+
+            fn abs(self) -> Self {
+                Self(Number::abs(self.0))
+            }
         }
     };
 }
@@ -48,3 +152,113 @@ number_impl!(i16, wrap);
 number_impl!(i32, wrap);
 number_impl!(i64, wrap);
 number_impl!(i128, wrap);
+
+#[cfg(test)]
+mod test {
+    use core::{cmp::Ordering, num::Wrapping};
+
+    use super::Number;
+
+    macro_rules! bounds_test {
+        ( $name:ident, $t:ty, $min:expr, $max:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(<$t as Number>::MIN, $min);
+                assert_eq!(<$t as Number>::MAX, $max);
+            }
+        };
+    }
+
+    bounds_test!(bounds_u8, u8, 0, 255);
+    bounds_test!(bounds_i32, i32, -2_147_483_648, 2_147_483_647);
+    bounds_test!(
+        bounds_wrapping_i8,
+        Wrapping<i8>,
+        Wrapping(-128),
+        Wrapping(127)
+    );
+    bounds_test!(
+        bounds_wrapping_u16,
+        Wrapping<u16>,
+        Wrapping(0),
+        Wrapping(65_535)
+    );
+
+    #[test]
+    fn compare_matches_ord() {
+        for (a, b) in [(1, 2), (2, 2), (3, 2), (-5, 5)] {
+            assert_eq!(Number::compare(&a, &b), a.cmp(&b));
+        }
+        assert_eq!(
+            Number::compare(&Wrapping(200_u8), &Wrapping(100)),
+            Ordering::Greater
+        );
+    }
+
+    macro_rules! clamped_test {
+        ( $name:ident, $value:expr, $expected:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(Number::to_usize_clamped($value), $expected);
+            }
+        };
+    }
+
+    clamped_test!(clamped_negative, -5_i32, 0);
+    clamped_test!(clamped_min, i128::MIN, 0);
+    clamped_test!(clamped_wrapping_negative, Wrapping(-1_i8), 0);
+    clamped_test!(clamped_in_range_signed, 12_i64, 12);
+    clamped_test!(clamped_in_range_unsigned, 200_u8, 200);
+    clamped_test!(clamped_too_large_signed, i128::MAX, usize::MAX);
+    clamped_test!(clamped_too_large_unsigned, u128::MAX, usize::MAX);
+    clamped_test!(clamped_wrapping_too_large, Wrapping(u128::MAX), usize::MAX);
+
+    macro_rules! saturating_test {
+        ( $name:ident, $t:ty, $value:expr, $expected:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(<$t as Number>::from_usize_saturating($value), $expected);
+            }
+        };
+    }
+
+    saturating_test!(saturating_in_range, i32, 12, 12);
+    saturating_test!(saturating_too_large, i8, 200, 127);
+    saturating_test!(saturating_max, u8, usize::MAX, 255);
+    saturating_test!(saturating_wrapping, Wrapping<i16>, 40_000, Wrapping(32_767));
+
+    macro_rules! wrapping_test {
+        ( $name:ident, $t:ty, $value:expr, $expected:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(<$t as Number>::from_u128_wrapping($value), $expected);
+            }
+        };
+    }
+
+    wrapping_test!(wrapping_in_range, u8, 200, 200);
+    wrapping_test!(wrapping_truncated, u8, 0x1_2345, 0x45);
+    wrapping_test!(wrapping_negative, i16, 0xFFFF, -1);
+    wrapping_test!(wrapping_max, u128, u128::MAX, u128::MAX);
+    wrapping_test!(wrapping_wrapping, Wrapping<i8>, 0x180, Wrapping(-128));
+
+    macro_rules! abs_test {
+        ( $name:ident, $value:expr, $expected:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(Number::abs($value), $expected);
+            }
+        };
+    }
+
+    abs_test!(abs_positive, 12_i32, 12);
+    abs_test!(abs_negative, -12_i32, 12);
+    abs_test!(abs_zero, 0_i64, 0);
+    abs_test!(abs_min, i8::MIN, i8::MIN);
+    abs_test!(abs_max, i128::MAX, i128::MAX);
+    abs_test!(abs_unsigned, 200_u8, 200);
+    abs_test!(abs_unsigned_max, u64::MAX, u64::MAX);
+    abs_test!(abs_wrapping_negative, Wrapping(-7_i16), Wrapping(7));
+    abs_test!(abs_wrapping_min, Wrapping(i32::MIN), Wrapping(i32::MIN));
+    abs_test!(abs_wrapping_unsigned, Wrapping(7_u16), Wrapping(7));
+}
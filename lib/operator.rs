@@ -1,12 +1,33 @@
 //! `MSCode` instructions for numerical and bitwise operations
 
-use crate::{stack::Stack, Number};
+use core::cmp::Ordering;
 
-#[derive(Clone, Copy)]
+use crate::{
+    instruction::{self, Instruction},
+    stack::Stack,
+    Number,
+};
+
+/// An instruction that operates on the register and the stack
+/// under the pointer
+///
+/// # Examples
+/// Pop the top value into the register, add the value below it and
+/// print the result
+/// ```
+/// use msc::{load, machine::State};
+///
+/// let mut machine = load::from_str("s 0 0 3 2\n.+p", &|value| value.parse::<i32>()).unwrap();
+///
+/// assert_eq!(machine.run_collect(), (vec![5], State::Stopped));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Operator {
     Push,
+    PushZero,
     Pop,
     Duplicate,
+    DuplicateN,
     Add,
     Subtract,
     Multiply,
@@ -15,22 +36,72 @@ pub enum Operator {
     Or,
     And,
     Xor,
+    Jump,
+    Tuck,
+    StackAdd,
+    StackSubtract,
+    StackMultiply,
+    StoreFlag,
+    LoadFlag,
+    PushX,
+    PushY,
+    PopIfZero,
+    Abs,
+    Reverse,
+    /// Select the register in the register file that
+    /// [`StoreRegister`](Operator::StoreRegister) and
+    /// [`LoadRegister`](Operator::LoadRegister) use, which is less
+    /// than [`REGISTER_COUNT`]
+    SelectRegister(usize),
+    StoreRegister,
+    LoadRegister,
+    Random,
+    Less,
+    Equal,
+    Greater,
+    Min,
+    Max,
 }
 
+/// The number of registers in the register file
+pub const REGISTER_COUNT: usize = 4;
+
+/// The most copies that [`Operator::DuplicateN`] pushes at once
+pub const MAX_DUPLICATE_N: usize = 1 << 16;
+
 impl Operator {
     #[must_use]
+    #[inline]
     pub fn apply<N: Number, StackType: Stack<Item = N>>(
         self,
         register: N,
         stack: &mut StackType,
     ) -> N {
-        use Operator::{Add, And, Divide, Duplicate, Multiply, Not, Or, Pop, Push, Subtract, Xor};
+        use Operator::{
+            Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+            LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY,
+            PushZero, Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract,
+            StoreFlag, StoreRegister, Subtract, Tuck, Xor,
+        };
         match self {
             Push => {
                 stack.push(register);
                 register
             }
+            PushZero => {
+                stack.push(register);
+                N::ZERO
+            }
             Pop => stack.pop().unwrap_or_default(),
+            // Only pop when the register is zero, so an empty stack
+            // leaves it as zero
+            PopIfZero => {
+                if register == N::ZERO {
+                    stack.pop().unwrap_or_default()
+                } else {
+                    register
+                }
+            }
             Duplicate => {
                 let value = stack.pop().map_or(N::ZERO, |value| {
                     stack.push(value);
@@ -39,6 +110,20 @@ impl Operator {
                 stack.push(value);
                 register
             }
+            DuplicateN => {
+                let value = stack.pop().unwrap_or_default();
+                stack.push(value);
+
+                // Pushing more than a bounded stack can hold has no effect
+                let count = register.to_usize_clamped().min(MAX_DUPLICATE_N);
+                let count = stack
+                    .capacity()
+                    .map_or(count, |capacity| count.min(capacity));
+                for _ in 0..count {
+                    stack.push(value);
+                }
+                register
+            }
             Add => register.add(stack.pop().unwrap_or_default()),
             Subtract => register.sub(stack.pop().unwrap_or_default()),
             Multiply => register.mul(stack.pop().unwrap_or(N::ONE)),
@@ -50,20 +135,206 @@ impl Operator {
                 register.div(rhs)
             }
             Not => register.not(),
+            Abs => register.abs(),
+            Reverse => {
+                stack.reverse();
+                register
+            }
             Or => register.bitor(stack.pop().unwrap_or_default()),
             And => register.bitand(stack.pop().unwrap_or_default()),
             Xor => register.bitxor(stack.pop().unwrap_or_default()),
+            Tuck => {
+                let top = stack.pop().unwrap_or_default();
+                let second = stack.pop().unwrap_or_default();
+                stack.push(top);
+                stack.push(second);
+                stack.push(top);
+                register
+            }
+            StackAdd => {
+                Self::apply_to_top_two(stack, N::ZERO, N::add);
+                register
+            }
+            StackSubtract => {
+                Self::apply_to_top_two(stack, N::ZERO, N::sub);
+                register
+            }
+            StackMultiply => {
+                Self::apply_to_top_two(stack, N::ONE, N::mul);
+                register
+            }
+            Min | Max | Less | Equal | Greater => self.apply_comparison(register, stack),
+            // The machine uses the flag register, the register file, the
+            // pointer and its random number generator, which are not seen here
+            StoreFlag | LoadFlag | SelectRegister(_) | StoreRegister | LoadRegister | PushX
+            | PushY | Random => register,
+            // The machine moves the pointer, this only pops the target
+            Jump => {
+                Self::pop_jump_target(stack);
+                register
+            }
         }
     }
+
+    /// Compare the register with the top value of the stack for the
+    /// [`Min`](Operator::Min), [`Max`](Operator::Max),
+    /// [`Less`](Operator::Less), [`Equal`](Operator::Equal) and
+    /// [`Greater`](Operator::Greater) operators
+    fn apply_comparison<N: Number, StackType: Stack<Item = N>>(
+        self,
+        register: N,
+        stack: &mut StackType,
+    ) -> N {
+        let expected = match self {
+            Self::Less => Ordering::Less,
+            Self::Equal => Ordering::Equal,
+            Self::Greater => Ordering::Greater,
+            // Leave the register unchanged if there is nothing to compare
+            _ => {
+                return stack.pop().map_or(register, |value| {
+                    let ordering = register.compare(&value);
+                    let keep = if matches!(self, Self::Min) {
+                        ordering.is_le()
+                    } else {
+                        ordering.is_ge()
+                    };
+                    if keep {
+                        register
+                    } else {
+                        value
+                    }
+                });
+            }
+        };
+
+        let ordering = register.compare(&stack.pop().unwrap_or_default());
+        stack.push(if ordering == expected {
+            N::ONE
+        } else {
+            N::ZERO
+        });
+        register
+    }
+
+    /// Pop the top value, then the second value off of the stack
+    /// (defaulting to `default` when missing) and push
+    /// `operation(second, top)`
+    fn apply_to_top_two<N: Number, StackType: Stack<Item = N>>(
+        stack: &mut StackType,
+        default: N,
+        operation: fn(N, N) -> N,
+    ) {
+        let [top, second] = stack.pop_n();
+        stack.push(operation(second.unwrap_or(default), top.unwrap_or(default)));
+    }
+
+    /// Get the number of values that the operator pops off of the stack
+    /// with the register, including any that it pushes back
+    #[must_use]
+    pub fn pop_count<N: Number>(self, register: N) -> usize {
+        use Operator::{
+            Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+            LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY,
+            PushZero, Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract,
+            StoreFlag, StoreRegister, Subtract, Tuck, Xor,
+        };
+        match self {
+            Push | PushZero | Not | Abs | Reverse | StoreFlag | LoadFlag | SelectRegister(_)
+            | StoreRegister | LoadRegister | PushX | PushY => 0,
+            PopIfZero => usize::from(register == N::ZERO),
+            Pop | Duplicate | DuplicateN | Add | Subtract | Multiply | Divide | Or | And | Xor
+            | Random | Less | Equal | Greater | Min | Max => 1,
+            Tuck | StackAdd | StackSubtract | StackMultiply | Jump => 2,
+        }
+    }
+
+    /// Pop the (x, y) target of a [`Jump`](Operator::Jump) off of the stack.
+    /// The y coordinate is popped first
+    #[inline]
+    pub fn pop_jump_target<N: Number, StackType: Stack<Item = N>>(stack: &mut StackType) -> (N, N) {
+        let [y, x] = stack.pop_n();
+        (x.unwrap_or_default(), y.unwrap_or_default())
+    }
+
+    /// Get the name of the operator, which is the name of its variant
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        use Operator::{
+            Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+            LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY,
+            PushZero, Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract,
+            StoreFlag, StoreRegister, Subtract, Tuck, Xor,
+        };
+        match self {
+            Push => "Push",
+            PushZero => "PushZero",
+            Pop => "Pop",
+            Duplicate => "Duplicate",
+            DuplicateN => "DuplicateN",
+            Add => "Add",
+            Subtract => "Subtract",
+            Multiply => "Multiply",
+            Divide => "Divide",
+            Not => "Not",
+            Or => "Or",
+            And => "And",
+            Xor => "Xor",
+            Jump => "Jump",
+            Tuck => "Tuck",
+            StackAdd => "StackAdd",
+            StackSubtract => "StackSubtract",
+            StackMultiply => "StackMultiply",
+            StoreFlag => "StoreFlag",
+            LoadFlag => "LoadFlag",
+            PushX => "PushX",
+            PushY => "PushY",
+            PopIfZero => "PopIfZero",
+            Abs => "Abs",
+            Reverse => "Reverse",
+            SelectRegister(register) => [
+                "SelectRegister0",
+                "SelectRegister1",
+                "SelectRegister2",
+                "SelectRegister3",
+            ][register % REGISTER_COUNT],
+            StoreRegister => "StoreRegister",
+            LoadRegister => "LoadRegister",
+            Random => "Random",
+            Less => "Less",
+            Equal => "Equal",
+            Greater => "Greater",
+            Min => "Min",
+            Max => "Max",
+        }
+    }
+
+    /// Get the operator with a [name](Operator::name), returning `None` if
+    /// there is none
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        instruction::ALL
+            .iter()
+            .find_map(|&instruction| match instruction {
+                Instruction::Operator(value) if value.name() == name => Some(value),
+                _ => None,
+            })
+    }
 }
 
 impl From<Operator> for char {
     fn from(val: Operator) -> Self {
-        use Operator::{Add, And, Divide, Duplicate, Multiply, Not, Or, Pop, Push, Subtract, Xor};
+        use Operator::{
+            Abs, Add, And, Divide, Duplicate, DuplicateN, Equal, Greater, Jump, Less, LoadFlag,
+            LoadRegister, Max, Min, Multiply, Not, Or, Pop, PopIfZero, Push, PushX, PushY,
+            PushZero, Random, Reverse, SelectRegister, StackAdd, StackMultiply, StackSubtract,
+            StoreFlag, StoreRegister, Subtract, Tuck, Xor,
+        };
         match val {
             Push => ',',
+            PushZero => ';',
             Pop => '.',
             Duplicate => 'd',
+            DuplicateN => 'D',
             Add => '+',
             Subtract => '-',
             Multiply => '*',
@@ -72,6 +343,27 @@ impl From<Operator> for char {
             Or => '|',
             And => '&',
             Xor => ':',
+            Jump => 'j',
+            Tuck => 't',
+            StackAdd => 'A',
+            StackSubtract => 'S',
+            StackMultiply => 'T',
+            StoreFlag => 'f',
+            LoadFlag => 'F',
+            PushX => 'x',
+            PushY => 'y',
+            PopIfZero => '?',
+            Abs => 'a',
+            Reverse => 'r',
+            SelectRegister(register) => Self::from(b"0123"[register % REGISTER_COUNT]),
+            StoreRegister => '[',
+            LoadRegister => ']',
+            Random => '%',
+            Less => 'L',
+            Equal => 'E',
+            Greater => 'G',
+            Min => 'm',
+            Max => 'M',
         }
     }
 }
@@ -80,7 +372,7 @@ impl From<Operator> for char {
 mod test {
     use crate::stack::{test_stacks::TestVecStack, Stack};
 
-    use super::Operator;
+    use super::{Operator, MAX_DUPLICATE_N};
 
     macro_rules! setup_stack {
         ( $name:ident, [] ) => {
@@ -107,12 +399,63 @@ mod test {
         };
     }
 
+    #[test]
+    fn pop_count() {
+        for (operation, zero, non_zero) in [
+            (Operator::Push, 0, 0),
+            (Operator::PushZero, 0, 0),
+            (Operator::Pop, 1, 1),
+            (Operator::PopIfZero, 1, 0),
+            (Operator::Duplicate, 1, 1),
+            (Operator::Add, 1, 1),
+            (Operator::Not, 0, 0),
+            (Operator::Tuck, 2, 2),
+            (Operator::StackAdd, 2, 2),
+            (Operator::Jump, 2, 2),
+            (Operator::PushX, 0, 0),
+            (Operator::Less, 1, 1),
+            (Operator::Min, 1, 1),
+        ] {
+            assert_eq!(operation.pop_count(0), zero, "{}", operation.name());
+            assert_eq!(operation.pop_count(5), non_zero, "{}", operation.name());
+        }
+    }
+
     operation_test!(push_empty, Operator::Push, 5, [], 5, [5]);
     operation_test!(push_non_empty, Operator::Push, 5, [10], 5, [10, 5]);
 
+    operation_test!(push_zero_empty, Operator::PushZero, 5, [], 0, [5]);
+    operation_test!(push_zero_non_empty, Operator::PushZero, 5, [10], 0, [10, 5]);
+
     operation_test!(pop_empty, Operator::Pop, 5, [], 0, []);
     operation_test!(pop_non_empty, Operator::Pop, 5, [20, 10], 10, [20]);
 
+    operation_test!(pop_if_zero_zero_empty, Operator::PopIfZero, 0, [], 0, []);
+    operation_test!(
+        pop_if_zero_zero_non_empty,
+        Operator::PopIfZero,
+        0,
+        [20, 10],
+        10,
+        [20]
+    );
+    operation_test!(
+        pop_if_zero_non_zero_empty,
+        Operator::PopIfZero,
+        5,
+        [],
+        5,
+        []
+    );
+    operation_test!(
+        pop_if_zero_non_zero_non_empty,
+        Operator::PopIfZero,
+        5,
+        [20, 10],
+        5,
+        [20, 10]
+    );
+
     operation_test!(duplicate_empty, Operator::Duplicate, 5, [], 5, [0]);
     operation_test!(
         duplicate_non_empty,
@@ -150,35 +493,186 @@ mod test {
     operation_test!(divide_non_empty, Operator::Divide, 10, [20, 2], 5, [20]);
     operation_test!(divide_zero, Operator::Divide, 5, [0], 5, []);
 
-    operation_test!(not, Operator::Not, 0b01100011u8, [], 0b10011100, []);
+    operation_test!(not, Operator::Not, 0b0110_0011_u8, [], 0b1001_1100, []);
+    operation_test!(abs_negative, Operator::Abs, -5_i8, [1], 5, [1]);
+    operation_test!(abs_min, Operator::Abs, -128_i8, [], -128, []);
 
-    operation_test!(or_empty, Operator::Or, 0b00111100u8, [], 0b00111100u8, []);
+    operation_test!(
+        or_empty,
+        Operator::Or,
+        0b0011_1100_u8,
+        [],
+        0b0011_1100_u8,
+        []
+    );
     operation_test!(
         or_non_empty,
         Operator::Or,
-        0b00111100u8,
-        [0b10000000, 0b10101010],
-        0b10111110,
-        [0b10000000]
+        0b0011_1100_u8,
+        [0b1000_0000, 0b1010_1010],
+        0b1011_1110,
+        [0b1000_0000]
     );
 
-    operation_test!(and_empty, Operator::And, 0b00111100u8, [], 0b00000000, []);
+    operation_test!(
+        and_empty,
+        Operator::And,
+        0b0011_1100_u8,
+        [],
+        0b0000_0000,
+        []
+    );
     operation_test!(
         and_non_empty,
         Operator::And,
-        0b00111100u8,
-        [0b10000000, 0b10101010],
-        0b00101000,
-        [0b10000000]
+        0b0011_1100_u8,
+        [0b1000_0000, 0b1010_1010],
+        0b0010_1000,
+        [0b1000_0000]
     );
 
-    operation_test!(xor, Operator::Xor, 0b00111100u8, [], 0b00111100u8, []);
+    operation_test!(xor, Operator::Xor, 0b0011_1100_u8, [], 0b0011_1100_u8, []);
     operation_test!(
         xor_non_empty,
         Operator::Xor,
-        0b00111100u8,
-        [0b10000000, 0b10101010],
-        0b10010110,
-        [0b10000000]
+        0b0011_1100_u8,
+        [0b1000_0000, 0b1010_1010],
+        0b1001_0110,
+        [0b1000_0000]
+    );
+
+    operation_test!(jump_empty, Operator::Jump, 5, [], 5, []);
+    operation_test!(jump_non_empty, Operator::Jump, 5, [20, 3, 4], 5, [20]);
+
+    operation_test!(reverse_empty, Operator::Reverse, 5, [], 5, []);
+    operation_test!(reverse_one, Operator::Reverse, 5, [10], 5, [10]);
+    operation_test!(
+        reverse_many,
+        Operator::Reverse,
+        5,
+        [10, 20, 30],
+        5,
+        [30, 20, 10]
+    );
+    operation_test!(less_less, Operator::Less, 5, [1, 10], 5, [1, 1]);
+    operation_test!(less_equal, Operator::Less, 5, [1, 5], 5, [1, 0]);
+    operation_test!(less_greater, Operator::Less, 5, [1, 2], 5, [1, 0]);
+    operation_test!(less_empty, Operator::Less, -5, [], -5, [1]);
+
+    operation_test!(equal_less, Operator::Equal, 5, [1, 10], 5, [1, 0]);
+    operation_test!(equal_equal, Operator::Equal, 5, [1, 5], 5, [1, 1]);
+    operation_test!(equal_greater, Operator::Equal, 5, [1, 2], 5, [1, 0]);
+    operation_test!(equal_empty, Operator::Equal, 0, [], 0, [1]);
+
+    operation_test!(greater_less, Operator::Greater, 5, [1, 10], 5, [1, 0]);
+    operation_test!(greater_equal, Operator::Greater, 5, [1, 5], 5, [1, 0]);
+    operation_test!(greater_greater, Operator::Greater, 5, [1, 2], 5, [1, 1]);
+    operation_test!(greater_empty, Operator::Greater, 5, [], 5, [1]);
+
+    operation_test!(min_smaller, Operator::Min, 5, [1, 10], 5, [1]);
+    operation_test!(min_larger, Operator::Min, 5, [1, 2], 2, [1]);
+    operation_test!(min_equal, Operator::Min, 5, [1, 5], 5, [1]);
+    operation_test!(min_empty, Operator::Min, 5, [], 5, []);
+
+    operation_test!(max_smaller, Operator::Max, 5, [1, 10], 10, [1]);
+    operation_test!(max_larger, Operator::Max, 5, [1, 2], 5, [1]);
+    operation_test!(max_equal, Operator::Max, 5, [1, 5], 5, [1]);
+    operation_test!(max_empty, Operator::Max, -5, [], -5, []);
+
+    operation_test!(tuck_empty, Operator::Tuck, 5, [], 5, [0, 0, 0]);
+    operation_test!(tuck_one, Operator::Tuck, 5, [10], 5, [10, 0, 10]);
+    operation_test!(tuck_two, Operator::Tuck, 5, [10, 20], 5, [20, 10, 20]);
+    operation_test!(
+        tuck_three,
+        Operator::Tuck,
+        5,
+        [1, 10, 20],
+        5,
+        [1, 20, 10, 20]
+    );
+
+    operation_test!(duplicate_n_zero, Operator::DuplicateN, 0, [1, 2], 0, [1, 2]);
+    operation_test!(
+        duplicate_n_negative,
+        Operator::DuplicateN,
+        -3,
+        [1, 2],
+        -3,
+        [1, 2]
+    );
+    operation_test!(
+        duplicate_n_one,
+        Operator::DuplicateN,
+        1,
+        [1, 2],
+        1,
+        [1, 2, 2]
+    );
+    operation_test!(
+        duplicate_n_three,
+        Operator::DuplicateN,
+        3,
+        [1, 2],
+        3,
+        [1, 2, 2, 2, 2]
+    );
+    operation_test!(duplicate_n_empty, Operator::DuplicateN, 2, [], 2, [0, 0, 0]);
+    operation_test!(duplicate_n_empty_zero, Operator::DuplicateN, 0, [], 0, [0]);
+
+    #[test]
+    fn duplicate_n_huge() {
+        let mut stack = TestVecStack::new();
+        stack.push(7_i64);
+
+        assert_eq!(Operator::DuplicateN.apply(i64::MAX, &mut stack), i64::MAX);
+        let stack = stack.destructure();
+        assert_eq!(stack.len(), MAX_DUPLICATE_N + 1);
+        assert!(stack.iter().all(|&value| value == 7));
+    }
+
+    operation_test!(store_flag, Operator::StoreFlag, 5, [10], 5, [10]);
+    operation_test!(load_flag, Operator::LoadFlag, 5, [10], 5, [10]);
+
+    operation_test!(push_x, Operator::PushX, 5, [10], 5, [10]);
+    operation_test!(push_y, Operator::PushY, 5, [10], 5, [10]);
+
+    operation_test!(stack_add_empty, Operator::StackAdd, 5, [], 5, [0]);
+    operation_test!(stack_add_one, Operator::StackAdd, 5, [10], 5, [10]);
+    operation_test!(stack_add_two, Operator::StackAdd, 5, [1, 10, 3], 5, [1, 13]);
+
+    operation_test!(stack_subtract_empty, Operator::StackSubtract, 5, [], 5, [0]);
+    operation_test!(
+        stack_subtract_one,
+        Operator::StackSubtract,
+        5,
+        [10],
+        5,
+        [-10]
+    );
+    operation_test!(
+        stack_subtract_two,
+        Operator::StackSubtract,
+        5,
+        [1, 10, 3],
+        5,
+        [1, 7]
+    );
+
+    operation_test!(stack_multiply_empty, Operator::StackMultiply, 5, [], 5, [1]);
+    operation_test!(
+        stack_multiply_one,
+        Operator::StackMultiply,
+        5,
+        [10],
+        5,
+        [10]
+    );
+    operation_test!(
+        stack_multiply_two,
+        Operator::StackMultiply,
+        5,
+        [1, 10, 3],
+        5,
+        [1, 30]
     );
 }
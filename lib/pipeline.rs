@@ -0,0 +1,170 @@
+//! Run `MSCode` machines in a chain, where the outputs of each machine
+//! are the inputs of the next.
+//! Requires std
+
+use std::collections::VecDeque;
+
+use crate::{
+    io::Channel,
+    load::Machine,
+    machine::{HaltReason, State},
+    Number,
+};
+
+/// A chain of machines, run in lockstep
+///
+/// The outputs of each machine on the [`Primary`](Channel::Primary)
+/// channel, including character outputs, are queued as the inputs of the
+/// next, and the outputs of the last machine are the outputs of the
+/// pipeline.
+///
+/// A machine waiting for input with nothing queued waits until the
+/// machine before it outputs. If the first machine is waiting, the
+/// pipeline is waiting for input from [`Pipeline::input`]
+pub struct Pipeline<N: Number> {
+    machines: Vec<Machine<N>>,
+    /// The queued inputs of each machine
+    queues: Vec<VecDeque<N>>,
+}
+
+impl<N: Number> Pipeline<N> {
+    #[must_use]
+    pub fn new(machines: Vec<Machine<N>>) -> Self {
+        let queues = machines.iter().map(|_| VecDeque::new()).collect();
+        Self { machines, queues }
+    }
+
+    /// Queue an input for the first machine
+    pub fn input(&mut self, input: N) {
+        if let Some(queue) = self.queues.first_mut() {
+            queue.push_back(input);
+        }
+    }
+
+    /// Step each machine once, from first to last, returning the output
+    /// of the last machine, if there was one
+    ///
+    /// # Panics
+    /// Panics if a machine's pointer is over a stack that is not in its
+    /// stack plane
+    pub fn step(&mut self) -> Option<N> {
+        let mut output = None;
+
+        for index in 0..self.machines.len() {
+            let value = self.machines[index]
+                .step_with_input(&mut self.queues[index])
+                .filter(|output| output.channel() == Channel::Primary)
+                .and_then(|output| output.value().copied());
+
+            if let Some(value) = value {
+                match self.queues.get_mut(index + 1) {
+                    Some(queue) => queue.push_back(value),
+                    None => output = Some(value),
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Step the pipeline until it is no longer [`State::Running`],
+    /// collecting the outputs of the last machine
+    ///
+    /// # Panics
+    /// Panics if a machine's pointer is over a stack that is not in its
+    /// stack plane
+    pub fn run_collect(&mut self) -> Vec<N> {
+        let mut outputs = Vec::new();
+        while matches!(self.get_state(), State::Running) {
+            outputs.extend(self.step());
+        }
+        outputs
+    }
+
+    /// Get the state of the pipeline:
+    /// - [`State::Running`] - a machine is running, or waiting with
+    ///   input queued
+    /// - [`State::InputWaiting`] - the first machine is waiting for input
+    /// - [`State::Halted`] - a machine halted, and nothing is left to run
+    /// - [`State::Stopped`] - every machine stopped, or is waiting for a
+    ///   machine that has stopped
+    #[must_use]
+    pub fn get_state(&self) -> State {
+        let mut halted: Option<HaltReason> = None;
+
+        for (machine, queue) in self.machines.iter().zip(&self.queues) {
+            match machine.get_state() {
+                State::Running => return State::Running,
+                State::InputWaiting if !queue.is_empty() => return State::Running,
+                State::Halted { reason } => halted = halted.or(Some(reason)),
+                State::InputWaiting | State::Stopped => {}
+            }
+        }
+
+        let waiting = self
+            .machines
+            .first()
+            .is_some_and(|machine| machine.get_state() == State::InputWaiting);
+        match halted {
+            Some(reason) => State::Halted { reason },
+            None if waiting => State::InputWaiting,
+            None => State::Stopped,
+        }
+    }
+
+    #[must_use]
+    pub fn machines(&self) -> &[Machine<N>] {
+        &self.machines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        load::{self, Machine},
+        machine::State,
+    };
+
+    use super::Pipeline;
+
+    fn load(source: &str) -> Machine<i32> {
+        load::from_str(source, &|value| value.parse()).unwrap()
+    }
+
+    /// Read a number and print it doubled, forever
+    const DOUBLE: &str = ">i,+v\n^  p<";
+    /// Read a number and print it plus one, forever
+    const ADD_ONE: &str = "s 0 0 1\n>id+v\n^  p<";
+
+    #[test]
+    fn double_then_add_one() {
+        let mut pipeline = Pipeline::new(vec![load(DOUBLE), load(ADD_ONE)]);
+        for input in [1, 2, 3] {
+            pipeline.input(input);
+        }
+
+        assert_eq!(pipeline.run_collect(), [3, 5, 7]);
+        // Waiting propagates back to the first machine
+        assert_eq!(pipeline.get_state(), State::InputWaiting);
+
+        pipeline.input(10);
+        assert_eq!(pipeline.run_collect(), [21]);
+    }
+
+    #[test]
+    fn upstream_stopped() {
+        // The first machine prints once and stops
+        let mut pipeline = Pipeline::new(vec![load("s 0 0 4\n.p"), load(ADD_ONE)]);
+
+        assert_eq!(pipeline.run_collect(), [5]);
+        assert_eq!(pipeline.get_state(), State::Stopped);
+    }
+
+    #[test]
+    fn empty() {
+        let mut pipeline = Pipeline::<i32>::new(Vec::new());
+
+        assert_eq!(pipeline.run_collect(), []);
+        assert_eq!(pipeline.get_state(), State::Stopped);
+    }
+}
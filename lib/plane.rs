@@ -1,6 +1,6 @@
 //! A 2d array-like system
 
-use crate::Pointer;
+use crate::{instruction::Instruction, Pointer};
 
 pub trait Plane {
     type Item;
@@ -10,15 +10,103 @@ pub trait Plane {
 
     fn get(&self, pointer: Pointer) -> Option<&Self::Item>;
     fn get_mut(&mut self, pointer: Pointer) -> Option<&mut Self::Item>;
+
+    /// Check if a pointer is within the width and height of the plane
+    fn in_bounds(&self, pointer: Pointer) -> bool {
+        pointer.0 < self.width() && pointer.1 < self.height()
+    }
+
+    /// Iterate over the items with their pointers, row by row
+    fn iter(&self) -> impl Iterator<Item = (Pointer, &Self::Item)> {
+        (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+            .filter_map(|pointer| Some((pointer, self.get(pointer)?)))
+    }
+
+    /// Set every item in the plane to its default, keeping the width
+    /// and height
+    fn clear(&mut self)
+    where
+        Self::Item: Default,
+    {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if let Some(item) = self.get_mut((x, y)) {
+                    *item = Self::Item::default();
+                }
+            }
+        }
+    }
+}
+
+/// Count the instructions in a plane that are not [`Space`](Instruction::Space)
+#[must_use]
+pub fn count_instructions(plane: &impl Plane<Item = Instruction>) -> usize {
+    plane
+        .iter()
+        .filter(|(_, instruction)| !matches!(instruction, Instruction::Space))
+        .count()
+}
+
+/// Check if any instruction in a plane matches `predicate`,
+/// stopping at the first match
+pub fn contains_instruction(
+    plane: &impl Plane<Item = Instruction>,
+    predicate: impl Fn(&Instruction) -> bool,
+) -> bool {
+    plane.iter().any(|(_, instruction)| predicate(instruction))
+}
+
+/// Check if the pointer may be able to move off of an edge of a plane
+/// of instructions, which is how most programs stop
+///
+/// This is a best-effort check of the cells on the edges. It is `false`
+/// only if every edge cell is an arrow that does not point off of the
+/// plane, so that the pointer can never leave over an edge. A `true`
+/// result does not mean that the pointer reaches an edge, and programs
+/// can also stop by jumping out of the plane. Empty planes can always
+/// be left
+#[must_use]
+pub fn has_edge_escape(plane: &impl Plane<Item = Instruction>) -> bool {
+    use crate::deflector::Deflector::{DownArrow, LeftArrow, RightArrow, UpArrow};
+
+    let (width, height) = (plane.width(), plane.height());
+    if width == 0 || height == 0 {
+        return true;
+    }
+
+    let top_and_bottom = (0..width).flat_map(|x| [(x, 0), (x, height - 1)]);
+    let sides = (0..height).flat_map(|y| [(0, y), (width - 1, y)]);
+    top_and_bottom.chain(sides).any(|(x, y)| {
+        // The velocities that move off of the plane from this cell
+        let outward = [
+            (x == width - 1).then_some(0b00),
+            (x == 0).then_some(0b01),
+            (y == height - 1).then_some(0b10),
+            (y == 0).then_some(0b11),
+        ];
+        match plane.get((x, y)) {
+            Some(Instruction::Deflector(
+                arrow @ (RightArrow | LeftArrow | DownArrow | UpArrow),
+            )) => outward.contains(&Some(arrow.apply(0))),
+            // Anything else may let the pointer through, or turn it off
+            // of the plane
+            _ => true,
+        }
+    })
 }
 
 #[cfg(feature = "std")]
 pub use std_planes::*;
 #[cfg(feature = "std")]
 mod std_planes {
+    use std::fmt::Write;
+
     use super::{Plane, Pointer};
+    use crate::instruction::Instruction;
 
     /// A growable, vector-based [`Plane`] implementation
+    #[derive(Clone, PartialEq, Eq, Debug)]
     pub struct VecPlane<T: Default>(usize, usize, Vec<Vec<T>>, T);
 
     impl<T: Default> Plane for VecPlane<T> {
@@ -32,22 +120,25 @@ mod std_planes {
             self.1
         }
 
+        #[inline]
         fn get(&self, pointer: Pointer) -> Option<&Self::Item> {
-            if pointer.0 >= self.0 || pointer.1 >= self.1 {
-                return None;
-            }
-            self.2.get(pointer.1).map_or(Some(&self.3), |row| {
-                Some(row.get(pointer.0).unwrap_or(&self.3))
-            })
+            // Check the rows first, as they are usually filled
+            self.2
+                .get(pointer.1)
+                .and_then(|row| row.get(pointer.0))
+                .or_else(|| self.in_bounds(pointer).then_some(&self.3))
         }
 
+        #[inline]
         fn get_mut(&mut self, pointer: Pointer) -> Option<&mut Self::Item> {
-            if pointer.0 >= self.0 || pointer.1 >= self.1 {
-                return None;
-            }
-            match self.2.get_mut(pointer.1) {
-                Some(row) => Some(row.get_mut(pointer.0).unwrap_or(&mut self.3)),
-                None => Some(&mut self.3),
+            match self
+                .2
+                .get_mut(pointer.1)
+                .and_then(|row| row.get_mut(pointer.0))
+            {
+                Some(item) => Some(item),
+                // Use the fields directly, as `self.2` is still borrowed
+                None => (pointer.0 < self.0 && pointer.1 < self.1).then_some(&mut self.3),
             }
         }
     }
@@ -62,22 +153,118 @@ mod std_planes {
         }
     }
 
+    impl<T: Default + PartialEq> VecPlane<T> {
+        /// Remove the trailing rows that only have default items, and
+        /// reduce the width to just fit the non-default items
+        pub fn trim(&mut self) {
+            let default = T::default();
+            while self
+                .2
+                .last()
+                .is_some_and(|row| row.iter().all(|item| *item == default))
+            {
+                self.2.pop();
+            }
+
+            let width = self
+                .2
+                .iter()
+                .filter_map(|row| row.iter().rposition(|item| *item != default))
+                .max()
+                .map_or(0, |x| x + 1);
+            for row in &mut self.2 {
+                row.truncate(width);
+            }
+
+            self.0 = width;
+            self.1 = self.2.len();
+        }
+    }
+
+    /// The height is the number of rows and the width is the length of the
+    /// longest row, so no rows or only empty rows give a width of 0.
+    /// Shorter rows are padded with the default item
     impl<T: Default + Clone> From<Vec<Vec<T>>> for VecPlane<T> {
         fn from(mut value: Vec<Vec<T>>) -> Self {
             let width = value
                 .iter()
                 .fold(0, |acc, row| if row.len() > acc { row.len() } else { acc });
 
-            value
-                .iter_mut()
-                .for_each(|row| row.extend(vec![T::default(); width - row.len()]));
+            for row in &mut value {
+                row.resize(width, T::default());
+            }
 
             Self(width, value.len(), value, T::default())
         }
     }
+
+    /// Create a copy of a plane with the rows and columns swapped,
+    /// so that the item at `(x, y)` moves to `(y, x)`
+    #[must_use]
+    pub fn transpose<T: Default + Clone>(plane: &impl Plane<Item = T>) -> VecPlane<T> {
+        (0..plane.width())
+            .map(|x| {
+                (0..plane.height())
+                    .map(|y| plane.get((x, y)).cloned().unwrap_or_default())
+                    .collect()
+            })
+            .collect::<Vec<Vec<T>>>()
+            .into()
+    }
+
+    /// List the cells where two planes of instructions differ, row by row,
+    /// with the instruction in `a` and then in `b`
+    ///
+    /// Cells outside of a plane are treated as [`Space`](Instruction::Space),
+    /// so planes of different sizes can be compared
+    #[must_use]
+    pub fn diff(
+        a: &impl Plane<Item = Instruction>,
+        b: &impl Plane<Item = Instruction>,
+    ) -> Vec<(Pointer, Instruction, Instruction)> {
+        let width = a.width().max(b.width());
+        let height = a.height().max(b.height());
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter_map(|pointer| {
+                let old = a.get(pointer).copied().unwrap_or(Instruction::Space);
+                let new = b.get(pointer).copied().unwrap_or(Instruction::Space);
+                (old != new).then_some((pointer, old, new))
+            })
+            .collect()
+    }
+
+    /// Render the instructions inside a box-drawing border, with the last
+    /// digit of each column above it and the number of each row to its left
+    #[must_use]
+    pub fn to_ascii_box(plane: &impl Plane<Item = Instruction>) -> String {
+        let (width, height) = (plane.width(), plane.height());
+        let margin = height.saturating_sub(1).to_string().len();
+        let border = "─".repeat(width);
+
+        let mut output = format!("{:margin$} ", "");
+        for x in 0..width {
+            let _ = write!(output, "{}", x % 10);
+        }
+        let _ = write!(output, "\n{:margin$}┌{border}┐", "");
+
+        // Write every row, even if the plane has no columns
+        for y in 0..height {
+            let _ = write!(output, "\n{y:>margin$}│");
+            for x in 0..width {
+                let instruction = plane.get((x, y)).copied().unwrap_or(Instruction::Space);
+                output.push(instruction.into());
+            }
+            output.push('│');
+        }
+
+        let _ = write!(output, "\n{:margin$}└{border}┘", "");
+        output
+    }
 }
 
 #[allow(clippy::module_name_repetitions)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 /// A constant-sized, array-based [`Plane`] implementation
 pub struct ArrayPlane<const WIDTH: usize, const HEIGHT: usize, T: Default>([[T; WIDTH]; HEIGHT], T);
 
@@ -92,23 +279,17 @@ impl<const WIDTH: usize, const HEIGHT: usize, T: Default> Plane for ArrayPlane<W
         HEIGHT
     }
 
+    #[inline]
     fn get(&self, pointer: Pointer) -> Option<&Self::Item> {
-        if pointer.0 >= WIDTH || pointer.1 >= HEIGHT {
-            return None;
-        }
-        self.0.get(pointer.1).map_or(Some(&self.1), |row| {
-            Some(row.get(pointer.0).unwrap_or(&self.1))
-        })
+        // The arrays are the width and height of the plane
+        self.0.get(pointer.1).and_then(|row| row.get(pointer.0))
     }
 
+    #[inline]
     fn get_mut(&mut self, pointer: Pointer) -> Option<&mut Self::Item> {
-        if pointer.0 >= WIDTH || pointer.1 >= HEIGHT {
-            return None;
-        }
-        match self.0.get_mut(pointer.1) {
-            Some(row) => Some(row.get_mut(pointer.0).unwrap_or(&mut self.1)),
-            None => Some(&mut self.1),
-        }
+        self.0
+            .get_mut(pointer.1)
+            .and_then(|row| row.get_mut(pointer.0))
     }
 }
 
@@ -137,16 +318,23 @@ impl<const WIDTH: usize, const HEIGHT: usize, T: Default + Copy> Default
 
 #[cfg(test)]
 mod test {
-    use crate::plane::ArrayPlane;
+    use crate::{
+        instruction::Instruction,
+        io::IO,
+        operator::Operator,
+        plane::{contains_instruction, count_instructions, has_edge_escape, ArrayPlane},
+    };
 
-    use super::{Plane, VecPlane};
+    use super::Plane;
+    #[cfg(feature = "std")]
+    use super::{diff, to_ascii_box, transpose, VecPlane};
 
     macro_rules! plane_ops {
         ( $plane:ident, set $pointer:expr => $value:literal ) => {
             *$plane.get_mut($pointer).unwrap() = $value
         };
         ( $plane:ident, get $pointer:expr => None ) => {
-            assert!(matches!($plane.get($pointer), None))
+            assert!($plane.get($pointer).is_none())
         };
         ( $plane:ident, get $pointer:expr => $value:literal ) => {
             assert!(matches!($plane.get($pointer), Some($value)))
@@ -172,29 +360,35 @@ mod test {
         };
     }
 
+    #[cfg(feature = "std")]
     plane_tests!(vec_empty, VecPlane<i8> => (4, 4),
         get (0, 0) => 0,
     );
+    #[cfg(feature = "std")]
     plane_tests!(vec_set_get, VecPlane<i8> => (4, 4),
         set (0, 0) => 5,
         get (0, 0) => 5,
     );
+    #[cfg(feature = "std")]
     plane_tests!(vec_set2_get, VecPlane<i8> => (4, 4),
         set (0, 0) => 5,
         set (1, 1) => 5,
         get (0, 0) => 5,
     );
+    #[cfg(feature = "std")]
     plane_tests!(vec_set2_get2, VecPlane<i8> => (4, 4),
         set (0, 0) => 5,
         set (1, 1) => 5,
         get (0, 0) => 5,
         get (1, 1) => 5,
     );
+    #[cfg(feature = "std")]
     plane_tests!(vec_set_get2, VecPlane<i8> => (4, 4),
         set (0, 0) => 5,
         get (0, 0) => 5,
         get (1, 1) => 0,
     );
+    #[cfg(feature = "std")]
     plane_tests!(vec_get_out_of_range, VecPlane<i8> => (4, 4),
         get (4, 0) => None,
         get (5, 0) => None,
@@ -235,4 +429,327 @@ mod test {
         get (4, 4,) => None,
         get (5, 5) => None,
     );
+
+    macro_rules! in_bounds_test {
+        ( $name:ident, $plane:expr ) => {
+            #[test]
+            fn $name() {
+                let plane = $plane;
+
+                let tests = [
+                    // Corners
+                    ((0, 0), true),
+                    ((3, 0), true),
+                    ((0, 2), true),
+                    ((3, 2), true),
+                    // Edges
+                    ((1, 0), true),
+                    ((0, 1), true),
+                    ((3, 1), true),
+                    ((1, 2), true),
+                    // Just outside
+                    ((4, 0), false),
+                    ((0, 3), false),
+                    ((4, 3), false),
+                    ((4, 2), false),
+                    ((3, 3), false),
+                    // Wrapped past zero
+                    ((usize::MAX, 0), false),
+                    ((0, usize::MAX), false),
+                ];
+
+                for (pointer, expected) in tests {
+                    assert_eq!(
+                        plane.in_bounds(pointer),
+                        expected,
+                        "in_bounds({pointer:?}) should be {expected}"
+                    );
+                }
+            }
+        };
+    }
+
+    #[cfg(feature = "std")]
+    in_bounds_test!(vec_in_bounds, VecPlane::<i8>::new(4, 3));
+    in_bounds_test!(array_in_bounds, ArrayPlane::<4, 3, i8>::new());
+
+    #[test]
+    fn iter() {
+        let plane = ArrayPlane::from([[1, 2], [3, 4], [5, 6]]);
+
+        assert!(plane.iter().eq([
+            ((0, 0), &1),
+            ((1, 0), &2),
+            ((0, 1), &3),
+            ((1, 1), &4),
+            ((0, 2), &5),
+            ((1, 2), &6),
+        ]));
+    }
+
+    #[test]
+    fn clear() {
+        let mut plane = ArrayPlane::from([[1, 2], [3, 4], [5, 6]]);
+        plane.clear();
+
+        assert_eq!((plane.width(), plane.height()), (2, 3));
+        assert!(plane.iter().all(|(_, &item)| item == 0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_clear() {
+        use crate::stack::{Stack, VecStack};
+
+        let mut plane = VecPlane::from(vec![vec![1, 2, 3], vec![4]]);
+        plane.clear();
+        assert_eq!((plane.width(), plane.height()), (3, 2));
+        assert_eq!(plane.iter().count(), 6);
+        assert!(plane.iter().all(|(_, &item)| item == 0));
+
+        let mut stack = VecStack::new();
+        stack.push(5);
+        let mut stacks = VecPlane::from(vec![vec![stack.clone(), stack]]);
+        stacks.clear();
+        assert!(stacks.iter().all(|(_, stack)| stack.is_empty()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_trim() {
+        let mut plane =
+            VecPlane::from(vec![vec![0, 1, 0, 0], vec![2], vec![0, 0, 0, 0, 0], vec![]]);
+        plane.trim();
+
+        assert_eq!((plane.width(), plane.height()), (2, 2));
+        assert!(plane.iter().map(|(_, &item)| item).eq([0, 1, 2, 0]));
+        assert!(plane.get((2, 0)).is_none());
+        assert!(plane.get((0, 2)).is_none());
+
+        let mut blank = VecPlane::from(vec![vec![0; 3]; 2]);
+        blank.trim();
+        assert_eq!((blank.width(), blank.height()), (0, 0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_trim_instructions() {
+        let machine =
+            crate::load::from_str("s 0 0 5\n.p   \n   \n\n", &|value| value.parse::<i32>())
+                .unwrap();
+        let mut instructions = machine.instructions().clone();
+        assert_eq!((instructions.width(), instructions.height()), (5, 3));
+
+        instructions.trim();
+        assert_eq!((instructions.width(), instructions.height()), (2, 1));
+        assert_eq!(instructions.get((1, 0)), Some(&Instruction::IO(IO::Print)));
+    }
+
+    #[test]
+    fn count() {
+        use Instruction::{Operator as Op, Space, IO as Io};
+
+        let plane = ArrayPlane::from([
+            [Op(Operator::Pop), Space, Io(IO::Print)],
+            [Space, Space, Space],
+            [Space, Op(Operator::Add), Space],
+        ]);
+
+        assert_eq!(count_instructions(&plane), 3);
+    }
+
+    #[test]
+    fn contains() {
+        use Instruction::{Operator as Op, Space, IO as Io};
+
+        let plane = ArrayPlane::from([[Op(Operator::Pop), Space], [Space, Io(IO::Print)]]);
+
+        assert!(contains_instruction(&plane, |instruction| matches!(
+            instruction,
+            Io(IO::Print)
+        )));
+        assert!(!contains_instruction(&plane, |instruction| matches!(
+            instruction,
+            Io(IO::Input)
+        )));
+    }
+
+    fn instructions<const WIDTH: usize, const HEIGHT: usize>(
+        rows: [[char; WIDTH]; HEIGHT],
+    ) -> ArrayPlane<WIDTH, HEIGHT, Instruction> {
+        ArrayPlane::from(rows.map(|row| row.map(|char| Instruction::try_from(char).unwrap())))
+    }
+
+    #[test]
+    fn edge_escape() {
+        assert!(has_edge_escape(&instructions([['>', '+', 'p']])));
+        // The last arrow points off of the right edge
+        assert!(has_edge_escape(&instructions([['>', '>', '>']])));
+        // The pointer may be turned off of the plane by the mirror
+        assert!(has_edge_escape(&instructions([['>', 'v'], ['^', '/'],])));
+        assert!(has_edge_escape(&ArrayPlane::<0, 0, Instruction>::new()));
+    }
+
+    #[test]
+    fn edge_trap() {
+        // Every edge cell points along the edge, clockwise
+        assert!(!has_edge_escape(&instructions([
+            ['>', '>', 'v'],
+            ['^', ' ', 'v'],
+            ['^', '<', '<'],
+        ])));
+        assert!(!has_edge_escape(&instructions([['>', '<']])));
+    }
+
+    #[test]
+    fn count_empty() {
+        let plane = ArrayPlane::<4, 4, Instruction>::new();
+
+        assert_eq!(count_instructions(&plane), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_from_no_rows() {
+        let mut plane = VecPlane::<i8>::from(Vec::new());
+
+        assert_eq!((plane.width(), plane.height()), (0, 0));
+        assert!(plane.get((0, 0)).is_none());
+        assert!(plane.get_mut((0, 0)).is_none());
+        assert_eq!(plane.iter().count(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_from_empty_rows() {
+        let mut plane = VecPlane::<i8>::from(vec![Vec::new(), Vec::new()]);
+
+        assert_eq!((plane.width(), plane.height()), (0, 2));
+        assert!(plane.get((0, 0)).is_none());
+        assert!(plane.get((0, 1)).is_none());
+        assert!(plane.get_mut((0, 1)).is_none());
+        assert_eq!(plane.iter().count(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_from_ragged_rows() {
+        let plane = VecPlane::from(vec![vec![1_i8], Vec::new(), vec![2, 3]]);
+
+        assert_eq!((plane.width(), plane.height()), (2, 3));
+        // Short rows are padded
+        assert_eq!(plane.get((1, 0)), Some(&0));
+        assert_eq!(plane.get((1, 1)), Some(&0));
+        assert_eq!(plane.get((1, 2)), Some(&3));
+        assert!(plane.get((2, 2)).is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_from_huge_row() {
+        let mut row = vec![0_i8; 1_000_000];
+        row[999_999] = 5;
+        let plane = VecPlane::from(vec![row]);
+
+        assert_eq!((plane.width(), plane.height()), (1_000_000, 1));
+        assert_eq!(plane.get((999_999, 0)), Some(&5));
+        assert!(plane.get((1_000_000, 0)).is_none());
+        assert!(plane.get((0, 1)).is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn transpose_instructions() {
+        use Instruction::{Operator as Op, Space, IO as Io};
+
+        let plane = ArrayPlane::from([
+            [Op(Operator::Pop), Space, Io(IO::Print)],
+            [Space, Op(Operator::Add), Space],
+        ]);
+        let transposed = transpose(&plane);
+
+        assert_eq!((transposed.width(), transposed.height()), (2, 3));
+        for ((x, y), instruction) in plane.iter() {
+            assert_eq!(
+                transposed.get((y, x)).copied().map(char::from),
+                Some(char::from(*instruction))
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn plane_diff() {
+        use Instruction::{Operator as Op, Space, IO as Io};
+
+        let plane = |source: &str| -> VecPlane<Instruction> {
+            source
+                .lines()
+                .map(|line| line.chars().map(|char| char.try_into().unwrap()).collect())
+                .collect::<Vec<Vec<Instruction>>>()
+                .into()
+        };
+        let old = plane(".+p\n v\n p");
+        let new = plane(".-p\n v\n p  ,");
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                ((1, 0), Op(Operator::Add), Op(Operator::Subtract)),
+                ((4, 2), Space, Op(Operator::Push)),
+            ]
+        );
+        assert_eq!(
+            diff(&new, &old)[0],
+            ((1, 0), Op(Operator::Subtract), Op(Operator::Add))
+        );
+        assert!(diff(&old, &old).is_empty());
+        // Missing cells are spaces
+        assert!(diff(&plane(" p"), &plane(" p  \n   ")).is_empty());
+        assert_eq!(
+            diff(&plane("p"), &VecPlane::from(Vec::new())),
+            [((0, 0), Io(IO::Print), Space)]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn ascii_box() {
+        use Instruction::{Operator as Op, Space, IO as Io};
+
+        let plane = ArrayPlane::from([
+            [Op(Operator::Pop), Space, Io(IO::Print)],
+            [Space, Op(Operator::Add), Space],
+        ]);
+
+        assert_eq!(
+            to_ascii_box(&plane),
+            "  012\n ┌───┐\n0│. p│\n1│ + │\n └───┘"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn ascii_box_no_columns() {
+        let plane = VecPlane::<Instruction>::from(vec![Vec::new(), Vec::new()]);
+
+        assert_eq!(to_ascii_box(&plane), "  \n ┌┐\n0││\n1││\n └┘");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn ascii_box_rulers() {
+        let plane = VecPlane::<Instruction>::new(12, 11);
+        let output = to_ascii_box(&plane);
+        let lines: Vec<&str> = output.lines().collect();
+
+        // The rulers, borders and rows
+        assert_eq!(lines.len(), 11 + 3);
+        assert_eq!(lines[0], "   012345678901");
+        assert!(lines[1].starts_with("  ┌"));
+        assert_eq!(lines[2], " 0│            │");
+        assert_eq!(lines[12], "10│            │");
+        // The lines below the column ruler all have the same width
+        assert!(lines[1..].iter().all(|line| line.chars().count() == 16));
+    }
 }
@@ -0,0 +1,524 @@
+//! `MSCode` programs, separate from the machines that run them
+
+use core::marker::PhantomData;
+
+use crate::{instruction::Instruction, machine::Machine, plane::Plane, stack::Stack, Number};
+
+/// The instructions and initial stacks of a program, which can be
+/// loaded once and run by many machines
+pub struct Program<N, InstructionPlane, StackPlane> {
+    instructions: InstructionPlane,
+    stacks: StackPlane,
+    number: PhantomData<N>,
+}
+
+impl<N, InstructionPlane, StackPlane> Program<N, InstructionPlane, StackPlane>
+where
+    N: Number,
+    InstructionPlane: Plane<Item = Instruction> + Clone,
+    StackPlane: Plane + Clone,
+    StackPlane::Item: Stack<Item = N>,
+{
+    /// Create a new program from instructions and initial stacks
+    #[must_use]
+    pub const fn new(instructions: InstructionPlane, stacks: StackPlane) -> Self {
+        Self {
+            instructions,
+            stacks,
+            number: PhantomData,
+        }
+    }
+
+    /// Get the instructions of the program
+    #[must_use]
+    pub const fn instructions(&self) -> &InstructionPlane {
+        &self.instructions
+    }
+
+    /// Get the stacks that machines start with
+    #[must_use]
+    pub const fn stacks(&self) -> &StackPlane {
+        &self.stacks
+    }
+
+    /// Create a new machine that runs the program from the start
+    #[must_use]
+    pub fn instantiate(&self) -> Machine<N, InstructionPlane, StackPlane::Item, StackPlane> {
+        Machine::new(self.instructions.clone(), self.stacks.clone())
+    }
+}
+
+impl<N, InstructionPlane: Clone, StackPlane: Clone> Clone
+    for Program<N, InstructionPlane, StackPlane>
+{
+    fn clone(&self) -> Self {
+        Self {
+            instructions: self.instructions.clone(),
+            stacks: self.stacks.clone(),
+            number: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use binary::*;
+#[cfg(feature = "std")]
+mod binary {
+    use core::{fmt::Display, num::ParseIntError, str::Utf8Error};
+    use std::error;
+
+    use crate::{
+        instruction::{Instruction, IntoInstructionError},
+        load::{self, FromStrRadix, Machine},
+        plane::{Plane, VecPlane},
+        stack::Stack,
+        Number, Pointer,
+    };
+
+    /// The bytes at the start of every binary program
+    pub const MAGIC: [u8; 4] = *b"MSCB";
+    /// The version of the binary format written by [`to_binary`]
+    pub const VERSION: u8 = 1;
+    /// The largest instruction plane, in cells, that [`from_binary`] will
+    /// decode, with an empty row counting as one cell
+    pub const MAX_AREA: usize = 1 << 24;
+
+    /// Binary program decoding errors
+    #[derive(Debug)]
+    pub enum BinaryError {
+        /// The bytes do not start with [`MAGIC`]
+        InvalidMagic,
+        /// A version other than [`VERSION`]
+        UnsupportedVersion(u8),
+        /// The bytes end part way through the program
+        UnexpectedEnd,
+        /// A variable-length integer that does not fit in a `usize`, or an
+        /// instruction plane larger than [`MAX_AREA`]
+        InvalidLength,
+        /// Invalid instruction character
+        InvalidInstruction(IntoInstructionError),
+        /// An empty run, or a run past the end of the instruction plane
+        InvalidRun,
+        /// A number that is not valid UTF-8
+        InvalidUtf8(Utf8Error),
+        /// Invalid number
+        InvalidNumber(ParseIntError),
+        /// Stack coordinate outside of the stack plane, with the width
+        /// and height of the stack plane
+        StackPointerOutOfRange(Pointer, (usize, usize)),
+        /// Bytes after the end of the program
+        TrailingBytes(usize),
+    }
+
+    impl error::Error for BinaryError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match self {
+                Self::InvalidInstruction(err) => Some(err),
+                Self::InvalidUtf8(err) => Some(err),
+                Self::InvalidNumber(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    impl Display for BinaryError {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            match self {
+                Self::InvalidMagic => write!(f, "not a binary MSCode program"),
+                Self::UnsupportedVersion(version) => {
+                    write!(f, "unsupported binary format version: {version}")
+                }
+                Self::UnexpectedEnd => write!(f, "unexpected end of binary program"),
+                Self::InvalidLength => write!(f, "length too large"),
+                Self::InvalidInstruction(err) => err.fmt(f),
+                Self::InvalidRun => write!(f, "instruction run out of range"),
+                Self::InvalidUtf8(err) => write!(f, "invalid number: {err}"),
+                Self::InvalidNumber(err) => write!(f, "invalid number: {err}"),
+                Self::StackPointerOutOfRange(pointer, (width, height)) => write!(
+                    f,
+                    "stack pointer out of range: {pointer:?}; valid range is 0..{width} × 0..{height}"
+                ),
+                Self::TrailingBytes(count) => {
+                    write!(f, "{count} unexpected bytes after the program")
+                }
+            }
+        }
+    }
+
+    /// Encode a machine's program in the compact binary format
+    ///
+    /// The stacks are encoded as they are now, so these are only the
+    /// seeded stacks until the machine has run. The registers, pointer
+    /// and state are not encoded
+    ///
+    /// The format is:
+    /// - the [`MAGIC`] bytes and the [`VERSION`] byte
+    /// - the width and height of the instruction plane
+    /// - runs of instructions in row order, each an instruction character
+    ///   (which are all ASCII) followed by the length of the run
+    /// - the number of non-empty stacks, then the x and y coordinates,
+    ///   length and items of each, bottom first
+    ///
+    /// Lengths and coordinates are LEB128 variable-length integers and
+    /// each item is its length followed by its decimal digits
+    #[must_use]
+    pub fn to_binary<N: Number + Display>(machine: &Machine<N>) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        let instructions = machine.instructions();
+        write_usize(&mut bytes, instructions.width());
+        write_usize(&mut bytes, instructions.height());
+
+        let mut run: Option<(char, usize)> = None;
+        for (_, &instruction) in instructions.iter() {
+            let instruction = char::from(instruction);
+            match &mut run {
+                Some((current, length)) if *current == instruction => *length += 1,
+                _ => {
+                    if let Some((current, length)) = run {
+                        write_run(&mut bytes, current, length);
+                    }
+                    run = Some((instruction, 1));
+                }
+            }
+        }
+        if let Some((current, length)) = run {
+            write_run(&mut bytes, current, length);
+        }
+
+        let stacks: Vec<_> = machine
+            .stacks()
+            .iter()
+            .filter(|(_, stack)| !stack.is_empty())
+            .collect();
+        write_usize(&mut bytes, stacks.len());
+        for ((x, y), stack) in stacks {
+            write_usize(&mut bytes, x);
+            write_usize(&mut bytes, y);
+            write_usize(&mut bytes, stack.len());
+            for item in stack.iter() {
+                let digits = item.to_string();
+                write_usize(&mut bytes, digits.len());
+                bytes.extend_from_slice(digits.as_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode a program in the binary format written by [`to_binary`]
+    ///
+    /// # Errors
+    /// - [`BinaryError::InvalidMagic`] - the bytes do not start with [`MAGIC`]
+    /// - [`BinaryError::UnsupportedVersion`] - the version is not [`VERSION`]
+    /// - [`BinaryError::UnexpectedEnd`] - the bytes end part way through the program
+    /// - [`BinaryError::InvalidLength`] - a length or coordinate does not fit in a `usize`,
+    ///   or the instruction plane is larger than [`MAX_AREA`]
+    /// - [`BinaryError::InvalidInstruction`] - failed to parse a character as an instruction
+    /// - [`BinaryError::InvalidRun`] - a run is empty or goes past the end of the instructions
+    /// - [`BinaryError::InvalidUtf8`] / [`BinaryError::InvalidNumber`] - failed to parse a number
+    /// - [`BinaryError::StackPointerOutOfRange`] - a stack coordinate is outside of the stack plane
+    /// - [`BinaryError::TrailingBytes`] - there are bytes after the program
+    pub fn from_binary<N: Number + FromStrRadix>(bytes: &[u8]) -> Result<Machine<N>, BinaryError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BinaryError::InvalidMagic);
+        }
+        match reader.byte()? {
+            VERSION => {}
+            version => return Err(BinaryError::UnsupportedVersion(version)),
+        }
+
+        let (width, height) = (reader.usize()?, reader.usize()?);
+        // Check the size before allocating, as empty rows are allocated too
+        width
+            .max(1)
+            .checked_mul(height)
+            .filter(|&area| area <= MAX_AREA)
+            .ok_or(BinaryError::InvalidLength)?;
+        let area = width * height;
+        let mut cells = Vec::new();
+        while cells.len() < area {
+            let instruction = Instruction::try_from(char::from(reader.byte()?))
+                .map_err(BinaryError::InvalidInstruction)?;
+            let length = reader.usize()?;
+            if length == 0 || length > area - cells.len() {
+                return Err(BinaryError::InvalidRun);
+            }
+            cells.resize(cells.len() + length, instruction);
+        }
+        let rows: Vec<Vec<Instruction>> = if width == 0 {
+            vec![Vec::new(); height]
+        } else {
+            cells.chunks(width).map(<[_]>::to_vec).collect()
+        };
+        let instructions = VecPlane::from(rows);
+
+        let mut stack_instructions = Vec::new();
+        for _ in 0..reader.usize()? {
+            let (x, y) = (reader.usize()?, reader.usize()?);
+            let mut stack = Vec::new();
+            for _ in 0..reader.usize()? {
+                let digits = reader.usize()?;
+                let digits =
+                    core::str::from_utf8(reader.take(digits)?).map_err(BinaryError::InvalidUtf8)?;
+                stack.push(N::from_str_radix(digits, 10).map_err(BinaryError::InvalidNumber)?);
+            }
+            stack_instructions.push((x, y, stack));
+        }
+        // Size the stack plane the same way as the text loader
+        let stacks =
+            load::create_stacks::<N, ParseIntError>(stack_instructions, &[], &instructions)
+                .map_err(|err| match err {
+                    load::Error::StackPointerOutOfRange(pointer, dimensions) => {
+                        BinaryError::StackPointerOutOfRange(pointer, dimensions)
+                    }
+                    _ => unreachable!("creating stacks only fails with out of range stacks"),
+                })?;
+
+        if !reader.0.is_empty() {
+            return Err(BinaryError::TrailingBytes(reader.0.len()));
+        }
+        Ok(Machine::new(instructions, stacks))
+    }
+
+    fn write_usize(bytes: &mut Vec<u8>, mut value: usize) {
+        loop {
+            // The low 7 bits, with the high bit set if there are more
+            let low = value.to_le_bytes()[0] & 0x7F;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(low);
+                return;
+            }
+            bytes.push(low | 0x80);
+        }
+    }
+
+    fn write_run(bytes: &mut Vec<u8>, char: char, length: usize) {
+        bytes.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+        write_usize(bytes, length);
+    }
+
+    /// Reads from the front of the bytes
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        const fn take(&mut self, count: usize) -> Result<&'a [u8], BinaryError> {
+            if count > self.0.len() {
+                return Err(BinaryError::UnexpectedEnd);
+            }
+            let (taken, rest) = self.0.split_at(count);
+            self.0 = rest;
+            Ok(taken)
+        }
+
+        fn byte(&mut self) -> Result<u8, BinaryError> {
+            Ok(self.take(1)?[0])
+        }
+
+        fn usize(&mut self) -> Result<usize, BinaryError> {
+            let mut value: usize = 0;
+            for shift in (0..usize::BITS).step_by(7) {
+                let byte = self.byte()?;
+                let low = usize::from(byte & 0x7F);
+                if shift > 0 && low.leading_zeros() < shift {
+                    return Err(BinaryError::InvalidLength);
+                }
+                value |= low << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(BinaryError::InvalidLength)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{
+        load::{self, Program},
+        machine::{HaltReason, State},
+        plane::Plane,
+        stack::Stack,
+    };
+
+    use super::{from_binary, to_binary, BinaryError, MAGIC, VERSION};
+
+    fn program(source: &str) -> Program<i32> {
+        load::from_str(source, &|value| value.parse::<i32>())
+            .unwrap()
+            .into_program()
+    }
+
+    #[test]
+    fn instantiate_independent() {
+        let program = program("s 0 0 2 3\n.p+p");
+        let mut first = program.instantiate();
+        let mut second = program.instantiate();
+
+        assert_eq!(first.run_collect(), (vec![3, 5], State::Stopped));
+        // Running the first machine does not change the second or the program
+        assert_eq!(second.get_steps(), 0);
+        assert!(second.current_stack().unwrap().iter().eq(&[2, 3]));
+        assert_eq!(second.run_collect(), (vec![3, 5], State::Stopped));
+        assert!(program.stacks().get((0, 0)).unwrap().iter().eq(&[2, 3]));
+    }
+
+    #[test]
+    fn reset() {
+        let program = program("s 0 0 2 3\n.p+p");
+        let mut machine = program.instantiate();
+        machine.set_step_limit(Some(3));
+        let halted = State::Halted {
+            reason: HaltReason::StepLimit,
+        };
+
+        assert_eq!(machine.run_collect(), (vec![3], halted));
+        machine.reset(&program);
+        assert_eq!(machine.get_state(), State::Running);
+        assert_eq!(machine.get_register(), 0);
+        assert_eq!(machine.get_steps(), 0);
+        // The step limit is kept
+        assert_eq!(machine.run_collect(), (vec![3], halted));
+    }
+
+    fn load(source: &str) -> load::Machine<i32> {
+        load::from_str(source, &|value| value.parse::<i32>()).unwrap()
+    }
+
+    fn chars(machine: &load::Machine<i32>) -> Vec<char> {
+        machine
+            .instructions()
+            .iter()
+            .map(|(_, &instruction)| char::from(instruction))
+            .collect()
+    }
+
+    fn assert_round_trip(source: &str) -> Vec<u8> {
+        let mut machine = load(source);
+        let bytes = to_binary(&machine);
+        let mut decoded = from_binary::<i32>(&bytes).unwrap();
+
+        assert_eq!(to_binary(&decoded), bytes);
+        assert_eq!(
+            decoded.instructions().width(),
+            machine.instructions().width()
+        );
+        assert_eq!(
+            decoded.instructions().height(),
+            machine.instructions().height()
+        );
+        assert_eq!(chars(&decoded), chars(&machine));
+        assert_eq!(decoded.stacks(), machine.stacks());
+        assert_eq!(decoded.run_collect(), machine.run_collect());
+        bytes
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        assert_round_trip("s 0 0 2 3\n.p+p");
+        assert_round_trip("");
+        assert_round_trip("\n\n");
+        assert_round_trip("s 1 2 5");
+    }
+
+    #[test]
+    fn binary_blank_runs() {
+        let source = format!(
+            "s 0 0 1 2\ns 10 1 -7 300\ns 0 1 -2147483648\n.p.p{}\n\n\n\n{}d+p",
+            " ".repeat(40),
+            " ".repeat(60)
+        );
+        let bytes = assert_round_trip(&source);
+
+        // The blank runs are much shorter than the source
+        assert!(bytes.len() < source.len() / 2, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn binary_header() {
+        let bytes = to_binary(&load(".p"));
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(bytes[4], VERSION);
+
+        let mut other = bytes.clone();
+        other[0] = b'X';
+        assert!(matches!(
+            from_binary::<i32>(&other),
+            Err(BinaryError::InvalidMagic)
+        ));
+        other = bytes;
+        other[4] = VERSION + 1;
+        assert!(matches!(
+            from_binary::<i32>(&other),
+            Err(BinaryError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn binary_errors() {
+        let bytes = to_binary(&load("s 0 0 5 6\n.p>"));
+
+        for end in 0..bytes.len() {
+            assert!(from_binary::<i32>(&bytes[..end]).is_err(), "{end} bytes");
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            from_binary::<i32>(&trailing),
+            Err(BinaryError::TrailingBytes(1))
+        ));
+
+        // A 1 by 1 program with a run of 2 spaces
+        let mut run = [&MAGIC[..], &[VERSION, 1, 1, b' ', 2, 0]].concat();
+        assert!(matches!(
+            from_binary::<i32>(&run),
+            Err(BinaryError::InvalidRun)
+        ));
+        run[7] = b'q';
+        assert!(matches!(
+            from_binary::<i32>(&run),
+            Err(BinaryError::InvalidInstruction(_))
+        ));
+
+        // A stack at (1, 0) in a 1 by 1 program
+        let stack = [&MAGIC[..], &[VERSION, 1, 1, b' ', 1, 1, 1, 0, 0]].concat();
+        assert!(matches!(
+            from_binary::<i32>(&stack),
+            Err(BinaryError::StackPointerOutOfRange((1, 0), (1, 1)))
+        ));
+
+        // A 0 by 2^40 program, and a 1 by 2^40 program with a single run
+        let huge = [0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        let empty_rows = [&MAGIC[..], &[VERSION, 0], &huge, &[0]].concat();
+        assert!(matches!(
+            from_binary::<i32>(&empty_rows),
+            Err(BinaryError::InvalidLength)
+        ));
+        let long_run = [&MAGIC[..], &[VERSION, 1], &huge, b" ", &huge, &[0]].concat();
+        assert!(matches!(
+            from_binary::<i32>(&long_run),
+            Err(BinaryError::InvalidLength)
+        ));
+
+        // A stack at (2^40, 0) in an empty program
+        let far_stack = [&MAGIC[..], &[VERSION, 0, 0, 1], &huge, &[0, 1, 1, b'1']].concat();
+        assert!(matches!(
+            from_binary::<i32>(&far_stack),
+            Err(BinaryError::StackPointerOutOfRange(
+                (0x100_0000_0000, 0),
+                (0, 0)
+            ))
+        ));
+
+        // A length that does not fit in a `usize`
+        let length = [&MAGIC[..], &[VERSION], &[0xFF; 12]].concat();
+        assert!(matches!(
+            from_binary::<i32>(&length),
+            Err(BinaryError::InvalidLength)
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! A small, seedable random number generator, for reproducible
+//! nondeterminism. Can be used with `no_std`
+
+use crate::{Number, Velocity};
+
+/// An xorshift random number generator with 64 bits of state.
+/// This is fast and reproducible, but not cryptographically secure
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Xorshift64(u64);
+
+impl Xorshift64 {
+    /// The seed used in place of 0, which would only generate 0
+    const ZERO_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    /// Create a generator from a seed. A seed of 0 is replaced,
+    /// as it would only generate 0
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self(if seed == 0 { Self::ZERO_SEED } else { seed })
+    }
+
+    /// Generate the next number
+    pub const fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Generate a number from the full range of `N`
+    pub fn next_n<N: Number>(&mut self) -> N {
+        let high = u128::from(self.next_u64()) << 64;
+        N::from_u128_wrapping(high | u128::from(self.next_u64()))
+    }
+
+    /// Generate a number from 0 up to, but not including, `bound`.
+    /// `bound` should fit in `N` and not be 0. The numbers are very
+    /// slightly biased towards 0 unless `bound` is a power of 2
+    pub fn next_below<N: Number>(&mut self, bound: usize) -> N {
+        let bound = u128::try_from(bound.max(1)).unwrap_or(u128::MAX);
+        N::from_u128_wrapping(u128::from(self.next_u64()) % bound)
+    }
+
+    /// Generate a random velocity, using the top 2 bits of the next number
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn next_velocity(&mut self) -> Velocity {
+        // Only 2 bits are left after the shift
+        (self.next_u64() >> 62) as Velocity
+    }
+}
+
+impl Default for Xorshift64 {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Xorshift64;
+
+    #[test]
+    fn sequence() {
+        let mut rng = Xorshift64::new(1);
+
+        assert_eq!(rng.next_u64(), 1_082_269_761);
+        assert_eq!(rng.next_u64(), 1_152_992_998_833_853_505);
+        assert_eq!(rng.next_u64(), 11_177_516_664_432_764_457);
+    }
+
+    #[test]
+    fn reproducible() {
+        let mut rng = Xorshift64::new(42);
+        let mut copy = rng.clone();
+
+        for _ in 0..100 {
+            assert_eq!(rng.next_u64(), copy.next_u64());
+        }
+    }
+
+    #[test]
+    fn next_below() {
+        let mut rng = Xorshift64::new(7);
+
+        for _ in 0..1000 {
+            let value: i8 = rng.next_below(6);
+            assert!((0..6).contains(&value));
+        }
+        assert_eq!(rng.next_below::<u8>(1), 0);
+    }
+
+    #[test]
+    fn next_n() {
+        let mut rng = Xorshift64::new(1);
+        let mut copy = rng.clone();
+
+        // The first number is made from the first two 64 bit numbers
+        let high = u128::from(copy.next_u64()) << 64;
+        assert_eq!(rng.next_n::<u128>(), high | u128::from(copy.next_u64()));
+        assert!((0..100).any(|_| rng.next_n::<i32>() < 0));
+    }
+
+    #[test]
+    fn zero_seed() {
+        let mut rng = Xorshift64::new(0);
+
+        assert_ne!(rng.next_u64(), 0);
+        assert_eq!(rng, {
+            let mut default = Xorshift64::default();
+            default.next_u64();
+            default
+        });
+    }
+
+    #[test]
+    fn velocity() {
+        let mut rng = Xorshift64::new(7);
+        let mut seen = [false; 4];
+
+        for _ in 0..1000 {
+            let velocity = rng.next_velocity();
+            assert!(velocity <= 0b11);
+            seen[usize::from(velocity)] = true;
+        }
+        // Every direction is generated
+        assert_eq!(seen, [true; 4]);
+    }
+}
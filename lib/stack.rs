@@ -5,6 +5,53 @@ pub trait Stack {
 
     fn push(&mut self, item: Self::Item);
     fn pop(&mut self) -> Option<Self::Item>;
+    /// Iterate over the items from the bottom to the top of the stack.
+    /// This has no default, so it must be implemented since version 0.4.0
+    fn iter(&self) -> impl Iterator<Item = &Self::Item>;
+    /// Reverse the order of the items, so the bottom item is on top.
+    /// This defaults to popping every item and pushing them back, which
+    /// needs the `std` feature; without it, the default does nothing
+    fn reverse(&mut self) {
+        #[cfg(feature = "std")]
+        {
+            let items: Vec<_> = core::iter::from_fn(|| self.pop()).collect();
+            for item in items {
+                self.push(item);
+            }
+        }
+    }
+
+    /// Push every item from `items` in order, so the last item is on top
+    fn push_all<I: IntoIterator<Item = Self::Item>>(&mut self, items: I)
+    where
+        Self: Sized,
+    {
+        for item in items {
+            self.push(item);
+        }
+    }
+
+    /// Pop up to `COUNT` items, top first. If there are fewer than
+    /// `COUNT` items, the rest are `None`
+    fn pop_n<const COUNT: usize>(&mut self) -> [Option<Self::Item>; COUNT] {
+        core::array::from_fn(|_| self.pop())
+    }
+
+    /// Get the number of items on the stack
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Check if the stack has no items
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the maximum number of items the stack can hold,
+    /// or `None` if it is unbounded
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
 }
 
 use core::ops::Rem;
@@ -15,7 +62,7 @@ pub use std_stacks::*;
 mod std_stacks {
     use super::Stack;
 
-    #[derive(Clone, Default)]
+    #[derive(Clone, Default, PartialEq, Eq, Debug)]
     /// A growable, vector-based [`Stack`] implementation
     pub struct VecStack<T: Default>(Vec<T>);
 
@@ -29,11 +76,34 @@ mod std_stacks {
         fn pop(&mut self) -> Option<Self::Item> {
             self.0.pop()
         }
+
+        fn push_all<I: IntoIterator<Item = Self::Item>>(&mut self, items: I) {
+            self.0.extend(items);
+        }
+
+        fn pop_n<const COUNT: usize>(&mut self) -> [Option<Self::Item>; COUNT] {
+            let split = self.0.len().saturating_sub(COUNT);
+            let mut popped = self.0.drain(split..).rev();
+            core::array::from_fn(|_| popped.next())
+        }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            self.0.iter()
+        }
+
+        fn reverse(&mut self) {
+            self.0.reverse();
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
     }
 
     impl<T: Default> VecStack<T> {
+        /// Push the items of `stack`, see [`Stack::push_all`]
         pub fn extend(&mut self, stack: Vec<T>) {
-            self.0.extend(stack);
+            self.push_all(stack);
         }
     }
 
@@ -42,11 +112,18 @@ mod std_stacks {
         pub const fn new() -> Self {
             Self(Vec::new())
         }
+
+        /// Create an empty stack with space allocated for `capacity` items.
+        /// This does not limit the size of the stack
+        #[must_use]
+        pub fn with_capacity(capacity: usize) -> Self {
+            Self(Vec::with_capacity(capacity))
+        }
     }
 }
 
 #[allow(clippy::module_name_repetitions)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 /// A constant-sized, vector-based [`Stack`] implementation
 pub struct ArrayStack<const CAPACITY: usize, T: Default + Copy>([Option<T>; CAPACITY], usize);
 
@@ -64,6 +141,37 @@ impl<const CAPACITY: usize, T: Default + Copy> Stack for ArrayStack<CAPACITY, T>
         self.0[self.1] = None;
         output
     }
+
+    fn pop_n<const COUNT: usize>(&mut self) -> [Option<Self::Item>; COUNT] {
+        // Walk down the ring once, leaving it as COUNT pops would
+        let mut index = self.1;
+        let output = core::array::from_fn(|_| {
+            index = index.checked_sub(1).unwrap_or(CAPACITY - 1);
+            self.0[index].take()
+        });
+        self.1 = index;
+        output
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+        // The oldest item is at or after the next push position
+        let (top, bottom) = self.0.split_at(self.1);
+        bottom.iter().chain(top).flatten()
+    }
+
+    fn reverse(&mut self) {
+        // The items are the `len` positions below the next push position,
+        // wrapping around the ring
+        let len = self.len();
+        let index = |offset: usize| (self.1 + CAPACITY - 1 - offset) % CAPACITY;
+        for offset in 0..len / 2 {
+            self.0.swap(index(offset), index(len - 1 - offset));
+        }
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(CAPACITY)
+    }
 }
 
 impl<const CAPACITY: usize, T: Default + Copy> ArrayStack<CAPACITY, T> {
@@ -83,11 +191,13 @@ impl<const CAPACITY: usize, T: Default + Copy> Default for ArrayStack<CAPACITY,
 mod test {
     use crate::stack::ArrayStack;
 
-    use super::{Stack, VecStack};
+    use super::Stack;
+    #[cfg(feature = "std")]
+    use super::VecStack;
 
     macro_rules! stack_ops {
         ( $stack:ident, pop None ) => {
-            assert!(matches!($stack.pop(), None))
+            assert!($stack.pop().is_none())
         };
         ( $stack:ident, pop $value:expr ) => {
             assert!(matches!($stack.pop(), Some($value)))
@@ -95,6 +205,18 @@ mod test {
         ( $stack:ident, push $value:expr ) => {
             $stack.push($value)
         };
+        ( $stack:ident, push_all $values:expr ) => {
+            $stack.push_all($values)
+        };
+        ( $stack:ident, pop_n [$( $value:expr ),*] ) => {
+            assert_eq!($stack.pop_n(), [$( $value ),*])
+        };
+        ( $stack:ident, reverse () ) => {
+            $stack.reverse()
+        };
+        ( $stack:ident, iter [$( $value:expr ),*] ) => {
+            assert!($stack.iter().copied().eq([$( $value ),*]))
+        };
     }
 
     macro_rules! stack_tests {
@@ -103,38 +225,181 @@ mod test {
             fn $name() {
                 let mut stack = <$type>::new();
 
+                $( stack_ops!(stack, $op $value) );* ;
+            }
+        };
+        ( $name:ident, $type:path = $init:expr, $( $op:tt $value:tt ),* , ) => {
+            #[test]
+            fn $name() {
+                let mut stack: $type = $init;
+
                 $( stack_ops!(stack, $op $value) );* ;
             }
         };
     }
 
+    #[cfg(feature = "std")]
     stack_tests!(vec_empty, VecStack<i8>,
         pop None,
     );
+    #[cfg(feature = "std")]
     stack_tests!(vec_push_pop, VecStack<i8>,
         push 5,
         pop 5,
     );
+    #[cfg(feature = "std")]
     stack_tests!(vec_push2_pop, VecStack<i8>,
         push 5,
         push 10,
         pop 10,
     );
+    #[cfg(feature = "std")]
     stack_tests!(vec_push2_pop2, VecStack<i8>,
         push 5,
         push 10,
         pop 10,
         pop 5,
     );
+    #[cfg(feature = "std")]
     stack_tests!(vec_push_pop2, VecStack<i8>,
         push 5,
         pop 5,
         pop None,
     );
 
+    #[cfg(feature = "std")]
+    stack_tests!(vec_iter, VecStack<i8>,
+        iter [],
+        push 5,
+        push 10,
+        iter [5, 10],
+        pop 10,
+        iter [5],
+    );
+
+    #[cfg(feature = "std")]
+    stack_tests!(vec_pop_n, VecStack<i8>,
+        push 5,
+        push 10,
+        push 15,
+        pop_n [Some(15), Some(10)],
+        iter [5],
+    );
+    #[cfg(feature = "std")]
+    stack_tests!(vec_pop_n_exact, VecStack<i8>,
+        push 5,
+        push 10,
+        pop_n [Some(10), Some(5)],
+        pop None,
+    );
+    #[cfg(feature = "std")]
+    stack_tests!(vec_pop_n_short, VecStack<i8>,
+        push 5,
+        pop_n [Some(5), None, None],
+        iter [],
+        push 10,
+        pop_n [Some(10)],
+    );
+
+    #[cfg(feature = "std")]
+    stack_tests!(vec_reverse, VecStack<i8>,
+        reverse (),
+        iter [],
+        push 5,
+        reverse (),
+        iter [5],
+        push 10,
+        push 15,
+        reverse (),
+        iter [15, 10, 5],
+        pop 5,
+    );
+
+    #[cfg(feature = "std")]
+    stack_tests!(vec_push_all_range, VecStack<i8>,
+        push 1,
+        push_all (2..5),
+        iter [1, 2, 3, 4],
+        pop 4,
+    );
+    #[cfg(feature = "std")]
+    stack_tests!(vec_push_all_slice, VecStack<i8>,
+        push_all ([5, 10].iter().copied()),
+        push_all [15],
+        iter [5, 10, 15],
+        pop 15,
+    );
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_len_capacity() {
+        let mut stack = VecStack::<i8>::new();
+        assert!(stack.is_empty());
+
+        stack.push(5);
+        stack.push(10);
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack.capacity(), None);
+    }
+
+    #[cfg(feature = "std")]
+    stack_tests!(vec_with_capacity, VecStack<i8> = VecStack::with_capacity(3),
+        push 5,
+        push 10,
+        push 15,
+        iter [5, 10, 15],
+        pop 15,
+        pop 10,
+        pop 5,
+        pop None,
+    );
+
+    #[cfg(feature = "std")]
+    stack_tests!(vec_with_capacity_exceeded, VecStack<i8> = VecStack::with_capacity(3),
+        push 5,
+        push 10,
+        push 15,
+        push 20,
+        iter [5, 10, 15, 20],
+        pop 20,
+    );
+
     stack_tests!(array_empty, ArrayStack<3, i8>,
         pop None,
     );
+
+    /// A stack that only implements the required methods
+    #[cfg(feature = "std")]
+    struct MinimalStack(Vec<i8>);
+
+    #[cfg(feature = "std")]
+    impl Stack for MinimalStack {
+        type Item = i8;
+
+        fn push(&mut self, item: Self::Item) {
+            self.0.push(item);
+        }
+
+        fn pop(&mut self) -> Option<Self::Item> {
+            self.0.pop()
+        }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            self.0.iter()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn default_reverse() {
+        let mut stack = MinimalStack(vec![5, 10, 15]);
+        stack.reverse();
+        assert_eq!(stack.0, [15, 10, 5]);
+
+        let mut empty = MinimalStack(Vec::new());
+        empty.reverse();
+        assert!(empty.is_empty());
+    }
     stack_tests!(array_push_pop, ArrayStack<3, i8>,
         push 5,
         pop 5,
@@ -165,12 +430,141 @@ mod test {
         pop 2,
         pop None,
     );
+    stack_tests!(array_iter, ArrayStack<3, i8>,
+        iter [],
+        push 5,
+        push 10,
+        iter [5, 10],
+        pop 10,
+        iter [5],
+    );
+    stack_tests!(array_overflow_iter, ArrayStack<3, i8>,
+        push 1,
+        push 2,
+        push 3,
+        push 4,
+        iter [2, 3, 4],
+        pop 4,
+        iter [2, 3],
+    );
+
+    stack_tests!(array_pop_n, ArrayStack<3, i8>,
+        push 5,
+        push 10,
+        push 15,
+        pop_n [Some(15), Some(10)],
+        iter [5],
+        push 20,
+        iter [5, 20],
+    );
+    stack_tests!(array_pop_n_exact, ArrayStack<3, i8>,
+        push 1,
+        push 2,
+        push 3,
+        push 4,
+        pop_n [Some(4), Some(3), Some(2)],
+        pop None,
+    );
+    stack_tests!(array_pop_n_short, ArrayStack<3, i8>,
+        push 5,
+        pop_n [Some(5), None],
+        iter [],
+    );
+
+    stack_tests!(array_reverse, ArrayStack<4, i8>,
+        reverse (),
+        iter [],
+        push 5,
+        reverse (),
+        iter [5],
+        push 10,
+        push 15,
+        reverse (),
+        iter [15, 10, 5],
+        pop 5,
+        push 20,
+        iter [15, 10, 20],
+    );
+    stack_tests!(array_reverse_wrapped, ArrayStack<3, i8>,
+        push 1,
+        push 2,
+        push 3,
+        push 4,
+        push 5,
+        reverse (),
+        iter [5, 4, 3],
+        pop 3,
+        pop 4,
+        pop 5,
+        pop None,
+    );
+    stack_tests!(array_reverse_partial, ArrayStack<5, i8>,
+        push 1,
+        push 2,
+        push 3,
+        push 4,
+        push 5,
+        push 6,
+        pop 6,
+        reverse (),
+        iter [5, 4, 3, 2],
+        pop 2,
+    );
+
+    stack_tests!(array_push_all_range, ArrayStack<4, i8>,
+        push 1,
+        push_all (2..5),
+        iter [1, 2, 3, 4],
+        pop 4,
+    );
+    stack_tests!(array_push_all_slice, ArrayStack<3, i8>,
+        push_all ([5, 10].iter().copied()),
+        push_all [15, 20],
+        iter [10, 15, 20],
+        pop 20,
+    );
+
+    #[test]
+    fn array_pop_n_matches_pop() {
+        let mut popped = ArrayStack::<3, i8>::new();
+        let mut stack = ArrayStack::<3, i8>::new();
+        for value in 1..=4 {
+            popped.push(value);
+            stack.push(value);
+        }
+
+        let values: [Option<i8>; 5] = popped.pop_n();
+        assert!(values.into_iter().eq((0..5).map(|_| stack.pop())));
+        assert_eq!(popped, stack);
+
+        popped.push(7);
+        stack.push(7);
+        assert_eq!(popped, stack);
+    }
+
+    #[test]
+    fn array_len_capacity() {
+        let mut stack = ArrayStack::<3, i8>::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.capacity(), Some(3));
+
+        stack.push(5);
+        stack.push(10);
+        assert_eq!(stack.len(), 2);
+
+        // Overflowing replaces the bottom item
+        stack.push(15);
+        stack.push(20);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.capacity(), Some(3));
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::new_without_default)]
 pub mod test_stacks {
-    use core::{marker::PhantomData, mem};
-    use std::thread::panicking;
+    use core::marker::PhantomData;
+    use std::{thread::panicking, vec::Vec};
 
     use super::Stack;
 
@@ -186,9 +580,18 @@ pub mod test_stacks {
         fn pop(&mut self) -> Option<Self::Item> {
             panic!("Attempted to pop from a fake stack!");
         }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            core::iter::empty()
+        }
+
+        fn reverse(&mut self) {
+            panic!("Attempted to reverse a fake stack!");
+        }
     }
 
     impl<N> FakeStack<N> {
+        #[must_use]
         pub const fn new() -> Self {
             Self(PhantomData)
         }
@@ -200,30 +603,40 @@ pub mod test_stacks {
         type Item = N;
 
         fn push(&mut self, item: Self::Item) {
-            if matches!(self.0, Some(_)) {
-                panic!("Attempted to push twice to a single-push stack!");
-            }
+            assert!(
+                self.0.is_none(),
+                "Attempted to push twice to a single-push stack!"
+            );
             self.0 = Some(item);
         }
 
         fn pop(&mut self) -> Option<Self::Item> {
             panic!("Attempted to pop from a single-push stack!");
         }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            self.0.iter()
+        }
+
+        fn reverse(&mut self) {}
     }
 
     impl<N> Drop for SinglePushStack<N> {
         fn drop(&mut self) {
-            if matches!(self.0, None) && !panicking() {
-                panic!("Did not push to a single-push stack!");
-            }
+            assert!(
+                self.0.is_some() || panicking(),
+                "Did not push to a single-push stack!"
+            );
         }
     }
 
     impl<N> SinglePushStack<N> {
+        #[must_use]
         pub const fn new() -> Self {
             Self(None)
         }
 
+        #[must_use]
         pub const fn get(&self) -> &Option<N> {
             &self.0
         }
@@ -239,22 +652,31 @@ pub mod test_stacks {
         }
 
         fn pop(&mut self) -> Option<Self::Item> {
-            if matches!(self.0, None) {
-                panic!("Attempted to pop twice from a single-pop stack!");
-            }
-            mem::replace(&mut self.0, None)
+            assert!(
+                self.0.is_some(),
+                "Attempted to pop twice from a single-pop stack!"
+            );
+            self.0.take()
         }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            self.0.iter()
+        }
+
+        fn reverse(&mut self) {}
     }
 
     impl<N> Drop for SinglePopStack<N> {
         fn drop(&mut self) {
-            if matches!(self.0, Some(_)) && !panicking() {
-                panic!("Did not pop from a single-pop stack!");
-            }
+            assert!(
+                self.0.is_none() || panicking(),
+                "Did not pop from a single-pop stack!"
+            );
         }
     }
 
     impl<N> SinglePopStack<N> {
+        #[must_use]
         pub const fn new(value: N) -> Self {
             Self(Some(value))
         }
@@ -272,13 +694,23 @@ pub mod test_stacks {
         fn pop(&mut self) -> Option<Self::Item> {
             self.0.pop()
         }
+
+        fn iter(&self) -> impl Iterator<Item = &Self::Item> {
+            self.0.iter()
+        }
+
+        fn reverse(&mut self) {
+            self.0.reverse();
+        }
     }
 
     impl<N> TestVecStack<N> {
+        #[must_use]
         pub const fn new() -> Self {
             Self(Vec::new())
         }
 
+        #[must_use]
         pub fn destructure(self) -> Vec<N> {
             self.0
         }
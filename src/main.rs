@@ -4,14 +4,16 @@ compile_error!("This binary must be compiled with the 'std' feature!\nTo use no_
 use msc::{
     self,
     instruction::Instruction,
-    load::{from_stdin, from_str},
+    io::{BufferedOutput, StdinInput, IO},
+    load::{from_file, from_stdin},
     machine::{Machine, State},
-    plane::VecPlane,
+    plane::{has_edge_escape, VecPlane},
     stack::VecStack,
 };
+mod repl;
+
 use std::{
-    env, fs,
-    io::{self, stdin, stdout, Write},
+    env, io,
     num::{ParseIntError, Wrapping},
 };
 
@@ -22,7 +24,11 @@ Usage: {} [options...] <files...>
 
   Options:
     -s, --suppress   Suppress errors and input prompts
+    -p, --prompt <prompt>
+                     Use <prompt> as the input prompt, '-p' must
+                     be last in a group, such as '-sp <prompt>'
     -S, --stdin      Force reading from stdin
+    -r, --repl       Start an interactive REPL, see ':help' inside it
     -h, --help       Display this message
     -v, --version    Print the version
     -a, --author     Information about the author
@@ -65,8 +71,32 @@ fn main() -> Result<(), ()> {
         cmd
     });
 
+    // Take out options with values, so that the values are not
+    // mistaken for files. '-p' can be the last of a group of short options
+    let mut prompt = None;
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        let short_prompt = arg.starts_with('-') && !arg.starts_with("--") && arg.ends_with('p');
+        if short_prompt || arg == "--prompt" {
+            let Some(value) = args.next() else {
+                eprintln!("Missing value for '{arg}'\n  Use '{cmd}--help' for help.");
+                return Err(());
+            };
+            prompt = Some(value);
+            // Keep the rest of the group
+            if short_prompt && arg.len() > 2 {
+                rest.push(arg[..arg.len() - 1].to_owned());
+            }
+        } else {
+            match arg.strip_prefix("--prompt=") {
+                Some(value) => prompt = Some(value.to_owned()),
+                None => rest.push(arg),
+            }
+        }
+    }
+
     // Split the args into files, long options, and short options
-    let (options, files): (Vec<String>, Vec<String>) = args.partition(|arg| arg.starts_with('-'));
+    let (options, files): (Vec<String>, Vec<String>) = rest.into_iter().partition(|arg| arg.starts_with('-'));
     let (long_options, short_options): (Vec<String>, Vec<String>) = options.into_iter().partition(|option| option.starts_with("--"));
     // Concatenate all the short options
     let short_options = short_options.into_iter().fold(String::new(), |mut all, options| {all += &options[1..]; all});
@@ -74,6 +104,7 @@ fn main() -> Result<(), ()> {
     // Set defaults
     let mut suppress = false;
     let mut force_stdin = false;
+    let mut do_repl = false;
     let mut do_help = false;
     let mut do_version = false;
     let mut do_author = false;
@@ -87,6 +118,9 @@ fn main() -> Result<(), ()> {
             'S' => {
                 force_stdin = true;
             }
+            'r' => {
+                do_repl = true;
+            }
             'h' => {
                 do_help = true
             }
@@ -96,6 +130,10 @@ fn main() -> Result<(), ()> {
             'a' => {
                 do_author = true
             }
+            'p' => {
+                eprintln!("'-p' must be the last of a group of short options\n  Use '{cmd}--help' for help.");
+                return Err(());
+            }
             _ => {
                 eprintln!("Unknown argument: '-{option}'\n  Use '{cmd}--help' for help.");
                 return Err(());
@@ -112,6 +150,9 @@ fn main() -> Result<(), ()> {
             "--stdin" => {
                 force_stdin = true;
             }
+            "--repl" => {
+                do_repl = true;
+            }
             "--help" => {
                 do_help = true;
             }
@@ -153,7 +194,7 @@ fn main() -> Result<(), ()> {
     }
 
     if meta {
-        if suppress || force_stdin || !files.is_empty() {
+        if suppress || force_stdin || do_repl || prompt.is_some() || !files.is_empty() {
             if space {
                 println!();
             }
@@ -164,6 +205,16 @@ fn main() -> Result<(), ()> {
         return Ok(());
     }
 
+    if do_repl {
+        if force_stdin || !files.is_empty() {
+            eprintln!("Files or --stdin provided with --repl! Ignoring.");
+        }
+
+        return repl::run(io::stdin().lock(), &mut io::stdout().lock(), !suppress).map_err(|err| {
+            c_eprintln!(!suppress => "{err}");
+        });
+    }
+
     if files.is_empty() || force_stdin {
         // Otherwise, read from stdin, like when the file is piped
         // in
@@ -176,7 +227,7 @@ fn main() -> Result<(), ()> {
             }
         };
 
-        if let Err(err) = run_machine(machine, true, suppress) {
+        if let Err(err) = run_machine(machine, true, suppress, prompt.as_deref()) {
             c_eprintln!(!suppress => "{err}");
             return Err(());
         };
@@ -186,15 +237,7 @@ fn main() -> Result<(), ()> {
         // If file paths were provided, like when executing files
         // with shebangs, run the files
         for path in files {
-            let file = match fs::read_to_string(path) {
-                Ok(file) => file,
-                Err(err) => {
-                    c_eprintln!(!suppress => "{err}");
-                    return Err(());
-                }
-            };
-
-            let machine = match from_str(&file, &parse_str_n) {
+            let machine = match from_file(&path, &parse_str_n) {
                 Ok(machine) => machine,
                 Err(err) => {
                     c_eprintln!(!suppress => "{err}");
@@ -202,7 +245,7 @@ fn main() -> Result<(), ()> {
                 }
             };
 
-            if let Err(err) = run_machine(machine, false, suppress) {
+            if let Err(err) = run_machine(machine, false, suppress, prompt.as_deref()) {
                 c_eprintln!(!suppress => "{err}");
                 return Err(());
             };
@@ -220,47 +263,60 @@ fn run_machine(
     mut machine: Machine<N, VecPlane<Instruction>, VecStack<N>, VecPlane<VecStack<N>>>,
     using_stdin: bool,
     suppress: bool,
+    prompt: Option<&str>,
+) -> Result<(), String> {
+    // Most programs stop by moving off of an edge
+    c_eprintln!(!suppress && !has_edge_escape(machine.instructions()) =>
+        "Warning: the pointer can never move off of the edges of the program, so it may never stop"
+    );
+
+    let mut input = StdinInput::new(&parse_str_n, !suppress);
+    if let Some(prompt) = prompt {
+        input = input.with_prompt(prompt);
+    }
+    let mut output = BufferedOutput::new(io::stdout().lock(), io::stderr());
+
+    let result = run_buffered(&mut machine, &mut input, &mut output, using_stdin);
+    // Write anything left in the buffer, even if the program failed
+    output.flush().map_err(|err| err.to_string())?;
+    result
+}
+
+fn run_buffered(
+    machine: &mut Machine<N, VecPlane<Instruction>, VecStack<N>, VecPlane<VecStack<N>>>,
+    input: &mut StdinInput<N, ParseIntError>,
+    output: &mut BufferedOutput<io::StdoutLock<'static>, io::Stderr>,
+    using_stdin: bool,
 ) -> Result<(), String> {
     loop {
         match machine.get_state() {
             State::Stopped => break,
             State::Running => {
-                if let Some(n) = machine.step() {
-                    println!("{n}");
+                // Show the outputs before prompting for input
+                if matches!(
+                    machine.peek_instruction(),
+                    Some(Instruction::IO(IO::Input | IO::InputChar | IO::InputAll))
+                ) {
+                    output.flush().map_err(|err| err.to_string())?;
+                }
+
+                if let Some(value) = machine.step_with_input(input) {
+                    output.emit(value).map_err(|err| err.to_string())?;
                 }
             }
             State::InputWaiting => {
-                if !suppress {
-                    print!("> ");
-                    if let Err(err) = stdout().flush() {
-                        return Err(err.to_string());
-                    };
-                }
-                let mut buffer = String::new();
-                if let Err(err) = stdin().read_line(&mut buffer) {
-                    return Err(err.to_string());
-                };
-                let buffer = buffer.trim_end();
-
-                // If the buffer is empty and the program was
-                // run from stdin, it is most likely that it
-                // was run through a pipe and cannot run
-                // interactively
-                if buffer.is_empty() && using_stdin {
+                // If the input ran out and the program was run
+                // from stdin, it is most likely that it was run
+                // through a pipe and cannot run interactively
+                if using_stdin {
                     return Err(
                         "Inputs cannot be used when the program is piped into the interpreter!\nRun the program by passing the file path as an argument.".to_owned()
                     );
                 }
 
-                machine.input(match parse_str_n(buffer) {
-                    Ok(value) => value,
-                    Err(err) => {
-                        println!("{buffer:?}");
-                        println!("{err}");
-                        continue;
-                    }
-                });
+                return Err("Input ended before the program finished!".to_owned());
             }
+            State::Halted { reason } => return Err(format!("Program halted: {reason}")),
         }
     }
 
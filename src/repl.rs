@@ -0,0 +1,334 @@
+//! An interactive mode that builds a program line by line
+//!
+//! Lines are read one at a time. A line starting with `:` is a command,
+//! any other line is a line of `MSCode` that is added to the program:
+//!
+//! - `:step [count]` - run `count` steps (default 1), showing each instruction
+//! - `:run [limit]` - run until the machine stops or waits for input,
+//!   or `limit` steps have run (default 1000000)
+//! - `:input <number>` - give the machine a number or character code that it is waiting for
+//! - `:dump` - show the pointer, register, state and current stack
+//! - `:reset` - start the program again from the beginning
+//! - `:list` - show the lines of the program
+//! - `:help` - show the commands
+//! - `:quit` - leave the REPL
+//!
+//! As `:` is also the Xor operator, a line starting with `::` adds the
+//! line without its first `:`, so `::p` adds `:p`.
+//!
+//! Adding a line rebuilds the machine, so it starts the program again.
+//! Outputs are shown as they happen
+
+use std::io::{self, BufRead, Write};
+
+use msc::{
+    io::Output,
+    load::{self, Parser},
+    machine::State,
+};
+
+use crate::{parse_str_n, N};
+
+const HELP_TEXT: &str = "\
+Lines starting with ':' are commands, other lines are added to the program.
+Start a line with '::' to add a line starting with ':' (Xor).
+  :step [count]    Run count steps (default 1)
+  :run [limit]     Run until stopped, waiting or limit steps (default 1000000)
+  :input <number>  Give the machine an input
+  :dump            Show the machine state
+  :reset           Start the program again
+  :list            Show the program
+  :help            Display this message
+  :quit            Leave the REPL
+";
+
+const DEFAULT_RUN_LIMIT: usize = 1_000_000;
+
+#[derive(Default)]
+struct Repl {
+    lines: Vec<String>,
+    /// The program and a machine running it, built when first needed
+    /// after the lines change
+    machine: Option<(load::Program<N>, load::Machine<N>)>,
+}
+
+/// Run the REPL until `input` ends or `:quit` is read, printing `msc> `
+/// before each line if `prompt` is set
+///
+/// # Errors
+/// Returns an error if reading or writing fails
+pub fn run(input: impl BufRead, output: &mut impl Write, prompt: bool) -> io::Result<()> {
+    let mut repl = Repl::default();
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(output, "msc> ")?;
+            output.flush()?;
+        }
+
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(());
+        };
+
+        match line.strip_prefix(':') {
+            // Escape a line of `MSCode` starting with Xor
+            Some(escaped) if escaped.starts_with(':') => {
+                repl.add_line(escaped.to_owned(), output)?;
+            }
+            Some(command) => {
+                if !repl.command(command, output)? {
+                    return Ok(());
+                }
+            }
+            None => repl.add_line(line, output)?,
+        }
+    }
+}
+
+impl Repl {
+    /// Add a line to the program, if the program still loads with it
+    fn add_line(&mut self, line: String, output: &mut impl Write) -> io::Result<()> {
+        let mut parser = Parser::new(&parse_str_n);
+        let result = self
+            .lines
+            .iter()
+            .chain([&line])
+            .try_for_each(|line| parser.feed_line(line));
+
+        match result {
+            Ok(()) => {
+                self.lines.push(line);
+                self.machine = None;
+                Ok(())
+            }
+            Err(err) => writeln!(output, "error: {err}"),
+        }
+    }
+
+    /// Get the machine, building it from the lines if they have changed
+    fn machine(&mut self, output: &mut impl Write) -> io::Result<Option<&mut load::Machine<N>>> {
+        if self.machine.is_none() {
+            let mut parser = Parser::new(&parse_str_n);
+            for line in &self.lines {
+                // The lines were checked when they were added
+                if parser.feed_line(line).is_err() {
+                    return Ok(None);
+                }
+            }
+
+            match parser.finish() {
+                Ok(machine) => {
+                    let program = machine.into_program();
+                    let machine = program.instantiate();
+                    self.machine = Some((program, machine));
+                }
+                Err(err) => {
+                    writeln!(output, "error: {err}")?;
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(self.machine.as_mut().map(|(_, machine)| machine))
+    }
+
+    /// Run a command, returning `false` if the REPL should stop
+    fn command(&mut self, command: &str, output: &mut impl Write) -> io::Result<bool> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+
+        match name {
+            "step" => {
+                let Some(count) = parse_count(argument, 1, output)? else {
+                    return Ok(true);
+                };
+                let Some(machine) = self.machine(output)? else {
+                    return Ok(true);
+                };
+
+                for _ in 0..count {
+                    if machine.get_state() != State::Running {
+                        break;
+                    }
+
+                    let info = machine.step_detailed();
+                    if let Some(instruction) = info.instruction {
+                        writeln!(output, "{:?} {:?}", info.pointer, char::from(instruction))?;
+                    }
+                    if let Some(value) = info.output.filter(|value| !matches!(value, Output::Flush))
+                    {
+                        writeln!(output, "{value}")?;
+                    }
+                }
+                write_state(machine.get_state(), output)?;
+            }
+            "run" => {
+                let Some(limit) = parse_count(argument, DEFAULT_RUN_LIMIT, output)? else {
+                    return Ok(true);
+                };
+                let Some(machine) = self.machine(output)? else {
+                    return Ok(true);
+                };
+
+                for _ in 0..limit {
+                    if machine.get_state() != State::Running {
+                        break;
+                    }
+
+                    if let Some(value) = machine
+                        .step()
+                        .filter(|value| !matches!(value, Output::Flush))
+                    {
+                        writeln!(output, "{value}")?;
+                    }
+                }
+                write_state(machine.get_state(), output)?;
+            }
+            "input" => {
+                let Some(value) = argument else {
+                    writeln!(output, "error: missing input")?;
+                    return Ok(true);
+                };
+                let value = match parse_str_n(value) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        writeln!(output, "error: {err}")?;
+                        return Ok(true);
+                    }
+                };
+                let Some(machine) = self.machine(output)? else {
+                    return Ok(true);
+                };
+
+                if machine.get_state() == State::InputWaiting {
+                    machine.input(value);
+                } else {
+                    writeln!(output, "error: the machine is not waiting for input")?;
+                }
+            }
+            "dump" => {
+                if let Some(machine) = self.machine(output)? {
+                    writeln!(output, "{}", machine.dump())?;
+                }
+            }
+            "reset" => {
+                // Building the machine starts it from the beginning
+                if let Some((program, machine)) = &mut self.machine {
+                    machine.reset(program);
+                }
+            }
+            "list" => {
+                for line in &self.lines {
+                    writeln!(output, "{line}")?;
+                }
+            }
+            "help" => write!(output, "{HELP_TEXT}")?,
+            "quit" => return Ok(false),
+            _ => writeln!(output, "unknown command: ':{name}', use ':help' for help")?,
+        }
+        Ok(true)
+    }
+}
+
+/// Parse the count argument of a command, or use `default` if there is none
+fn parse_count(
+    argument: Option<&str>,
+    default: usize,
+    output: &mut impl Write,
+) -> io::Result<Option<usize>> {
+    match argument.map(str::parse) {
+        None => Ok(Some(default)),
+        Some(Ok(count)) => Ok(Some(count)),
+        Some(Err(err)) => {
+            writeln!(output, "error: {err}")?;
+            Ok(None)
+        }
+    }
+}
+
+/// Show the state of the machine, unless it is still running
+fn write_state(state: State, output: &mut impl Write) -> io::Result<()> {
+    match state {
+        State::Running => Ok(()),
+        State::Stopped => writeln!(output, "stopped"),
+        State::InputWaiting => writeln!(output, "waiting for input"),
+        State::Halted { reason } => writeln!(output, "halted: {reason}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::run;
+
+    fn repl(script: &str) -> String {
+        let mut output = Vec::new();
+        run(script.as_bytes(), &mut output, false).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_and_run() {
+        let output = repl("s 0 0 5\n.+p\n:step\n:step 1\n:run\n:step\n");
+
+        assert_eq!(output, "(0, 0) '.'\n(1, 0) '+'\n5\nstopped\nstopped\n");
+    }
+
+    #[test]
+    fn step_shows_outputs() {
+        let output = repl("s 0 0 5\n.p\n:step 5\n");
+
+        assert_eq!(output, "(0, 0) '.'\n(1, 0) 'p'\n5\nstopped\n");
+    }
+
+    #[test]
+    fn reset() {
+        let output = repl("s 0 0 5\n.p\n:run\n:reset\n:run\n:quit\n:run\n");
+
+        assert_eq!(output, "5\nstopped\n5\nstopped\n");
+    }
+
+    #[test]
+    fn input() {
+        let output = repl("i,+p\n:run\n:input 21\n:input 1\n:run\n");
+
+        assert_eq!(
+            output,
+            "waiting for input\nerror: the machine is not waiting for input\n42\nstopped\n"
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let output = repl("s 0 0 5\n.p\n:step\n:dump\n");
+
+        assert_eq!(
+            output,
+            "(0, 0) '.'\npointer: (1, 0)\nvelocity: > (Right)\nregister: 5\nstate: Running\nstack: []\ninstruction: 'p'\n"
+        );
+    }
+
+    #[test]
+    fn adding_lines_restarts() {
+        let output = repl("s 0 0 5\n.v\n:run\n p\n:run\n:list\n");
+
+        assert_eq!(output, "stopped\n5\nstopped\ns 0 0 5\n.v\n p\n");
+    }
+
+    #[test]
+    fn escaped_xor_line() {
+        let output = repl("s 0 0 6\n::p\n:run\n:list\n");
+
+        assert_eq!(output, "6\nstopped\ns 0 0 6\n:p\n");
+    }
+
+    #[test]
+    fn errors() {
+        let output = repl("_\n:step x\n:input\n:frobnicate\n:list\n");
+
+        assert_eq!(
+            output,
+            "error: unknown instruction: '_' (U+005F)\nerror: invalid digit found in string\nerror: missing input\nunknown command: ':frobnicate', use ':help' for help\n"
+        );
+    }
+}